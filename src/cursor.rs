@@ -0,0 +1,218 @@
+// MIT License
+
+// Copyright (c) 2016 Jerome Froelich
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A cursor over the recency list of an `LruCache`.
+
+#[cfg(feature = "allocator-api")]
+use allocator_api2::alloc::Allocator;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{Epoch, EpochPolicy, LruCache, LruEntry, RemovalCause};
+
+/// A cursor over the entries of an `LruCache` that can move in both directions and change the
+/// entry it points at.
+///
+/// Moving to the next entry walks towards the least recently used end of the list and moving
+/// to the previous entry walks towards the most recently used end. Past either end the cursor
+/// points at a "ghost" position that holds no entry; moving on from there wraps around to the
+/// other end. The LRU list is left untouched by moving the cursor and by looking at entries.
+///
+/// This `struct` is created by the [`cursor_mru`] and [`cursor_lru`] methods on [`LruCache`].
+///
+/// # Example
+///
+/// ```
+/// use lru::LruCache;
+/// let mut cache = LruCache::new(4);
+///
+/// cache.put("a", 30);
+/// cache.put("b", 10);
+/// cache.put("c", 20);
+///
+/// // evict the cheapest of the two least recently used entries
+/// let mut cursor = cache.cursor_lru();
+/// let mut cheapest = (*cursor.key().unwrap(), *cursor.value_mut().unwrap());
+/// cursor.move_prev();
+/// if *cursor.value_mut().unwrap() < cheapest.1 {
+///     cheapest = (*cursor.key().unwrap(), *cursor.value_mut().unwrap());
+/// }
+/// drop(cursor);
+/// cache.pop(&cheapest.0);
+///
+/// assert_eq!(cache.iter().collect::<Vec<_>>(), vec![(&"c", &20), (&"a", &30)]);
+/// ```
+///
+/// [`cursor_mru`]: struct.LruCache.html#method.cursor_mru
+/// [`cursor_lru`]: struct.LruCache.html#method.cursor_lru
+/// [`LruCache`]: struct.LruCache.html
+pub struct CursorMut<'a, K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> {
+    cache: &'a mut with_alloc!(LruCache<K, V, S, E>),
+    // null at the ghost position
+    node: *mut LruEntry<K, V, E>,
+}
+
+impl<
+        'a,
+        K: Hash + Eq,
+        V,
+        S: BuildHasher,
+        E: Epoch,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator,
+    > with_alloc!(CursorMut<'a, K, V, S, E>)
+{
+    pub(crate) fn new(
+        cache: &'a mut with_alloc!(LruCache<K, V, S, E>),
+        node: *mut LruEntry<K, V, E>,
+    ) -> Self {
+        CursorMut { cache, node }
+    }
+
+    /// Moves the cursor to the next, less recently used entry. From the least recently used
+    /// entry it moves to the ghost position, and from there to the most recently used entry.
+    pub fn move_next(&mut self) {
+        let from = if self.node.is_null() {
+            self.cache.head
+        } else {
+            self.node
+        };
+        self.node = self.cache.next_entry(from);
+    }
+
+    /// Moves the cursor to the previous, more recently used entry. From the most recently used
+    /// entry it moves to the ghost position, and from there to the least recently used entry.
+    pub fn move_prev(&mut self) {
+        let from = if self.node.is_null() {
+            self.cache.tail
+        } else {
+            self.node
+        };
+        self.node = self.cache.prev_entry(from);
+    }
+
+    /// Returns whether the cursor is at the ghost position, which is also where it starts out
+    /// in an empty cache.
+    pub fn is_ghost(&self) -> bool {
+        self.node.is_null()
+    }
+
+    /// Returns the key of the current entry, or `None` at the ghost position.
+    pub fn key(&self) -> Option<&K> {
+        if self.node.is_null() {
+            return None;
+        }
+        Some(unsafe { &*(*self.node).key.as_ptr() })
+    }
+
+    /// Returns a mutable reference to the value of the current entry, or `None` at the ghost
+    /// position.
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        if self.node.is_null() {
+            return None;
+        }
+        Some(unsafe { &mut *(*self.node).val.as_mut_ptr() })
+    }
+
+    /// Returns the key and a mutable reference to the value of the current entry, or `None` at
+    /// the ghost position.
+    pub fn current(&mut self) -> Option<(&K, &mut V)> {
+        if self.node.is_null() {
+            return None;
+        }
+        unsafe {
+            Some((
+                &*(*self.node).key.as_ptr(),
+                &mut *(*self.node).val.as_mut_ptr(),
+            ))
+        }
+    }
+
+    /// Returns the epoch of the current entry, or `None` at the ghost position.
+    pub fn epoch(&self) -> Option<E> {
+        if self.node.is_null() {
+            return None;
+        }
+        Some(unsafe { (*self.node).epoch })
+    }
+
+    /// Removes the current entry from the cache and returns it, moving the cursor to the next,
+    /// less recently used entry. The entry is reported to the eviction listener as an explicit
+    /// removal, even if it is pinned. Returns `None` at the ghost position.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(3);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.put(3, "c");
+    ///
+    /// let mut cursor = cache.cursor_mru();
+    /// cursor.move_next();
+    /// assert_eq!(cursor.remove_current(), Some((2, "b")));
+    /// assert_eq!(cursor.key(), Some(&1));
+    /// ```
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        if self.node.is_null() {
+            return None;
+        }
+        let node = self.node;
+        self.node = self.cache.next_entry(node);
+        Some(self.cache.evict_node(node, RemovalCause::Explicit))
+    }
+
+    /// Moves the current entry to the most recently used end of the list, as if it had been
+    /// accessed by `get`. In a segmented cache that promotes it into the protected segment,
+    /// unless it is still in the admission window. The cursor stays on the entry. Does nothing
+    /// at the ghost position.
+    pub fn move_current_to_mru(&mut self) {
+        if self.node.is_null() {
+            return;
+        }
+        let refresh = self.cache.epoch_policy == EpochPolicy::RefreshOnRead;
+        self.cache.touch(self.node, refresh);
+    }
+
+    /// Moves the current entry to the least recently used end of the list, so that it is the
+    /// next one to be evicted unless it is pinned. The cursor stays on the entry. Does nothing
+    /// at the ghost position.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.cursor_mru().move_current_to_lru();
+    ///
+    /// assert_eq!(cache.peek_lru(), Some((&2, &"b")));
+    /// ```
+    pub fn move_current_to_lru(&mut self) {
+        if self.node.is_null() {
+            return;
+        }
+        self.cache.move_to_tail(self.node);
+    }
+}
@@ -0,0 +1,345 @@
+// MIT License
+
+// Copyright (c) 2016 Jerome Froelich
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The entry API of `LruCache`, modeled after `hashbrown::hash_map::Entry`.
+
+#[cfg(feature = "allocator-api")]
+use allocator_api2::alloc::Allocator;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use crate::{Epoch, EpochPolicy, LruCache, LruEntry, RemovalCause};
+
+/// A view into a single entry of an `LruCache`, which may either be vacant or occupied.
+///
+/// This `enum` is created by the [`entry`] and [`peek_entry`] methods on [`LruCache`].
+///
+/// [`entry`]: struct.LruCache.html#method.entry
+/// [`peek_entry`]: struct.LruCache.html#method.peek_entry
+/// [`LruCache`]: struct.LruCache.html
+pub enum Entry<'a, K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> {
+    /// An entry whose key is in the cache.
+    Occupied(with_alloc!(OccupiedEntry<'a, K, V, S, E>)),
+    /// An entry whose key is not in the cache.
+    Vacant(with_alloc!(VacantEntry<'a, K, V, S, E>)),
+}
+
+/// A view into an occupied entry of an `LruCache`. It is part of the [`Entry`] enum.
+///
+/// [`Entry`]: enum.Entry.html
+pub struct OccupiedEntry<'a, K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> {
+    cache: &'a mut with_alloc!(LruCache<K, V, S, E>),
+    node: *mut LruEntry<K, V, E>,
+    hash: u64,
+}
+
+/// A view into a vacant entry of an `LruCache`. It is part of the [`Entry`] enum.
+///
+/// [`Entry`]: enum.Entry.html
+pub struct VacantEntry<'a, K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> {
+    cache: &'a mut with_alloc!(LruCache<K, V, S, E>),
+    key: K,
+    hash: u64,
+}
+
+impl<
+        'a,
+        K: Hash + Eq,
+        V,
+        S: BuildHasher,
+        E: Epoch,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator,
+    > with_alloc!(Entry<'a, K, V, S, E>)
+{
+    /// Ensures a value is in the entry by inserting the default if it is vacant, and returns a
+    /// mutable reference to the value in the entry.
+    ///
+    /// Returns `None` only if the entry is vacant and the capacity of the cache is 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// *cache.entry("apple").or_insert(0).unwrap() += 1;
+    /// *cache.entry("apple").or_insert(0).unwrap() += 1;
+    /// assert_eq!(cache.get(&"apple"), Some(&2));
+    /// ```
+    pub fn or_insert(self, default: V) -> Option<&'a mut V> {
+        match self {
+            Entry::Occupied(entry) => Some(entry.into_mut()),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of `f` if it is vacant, and
+    /// returns a mutable reference to the value in the entry. `f` is not called if the entry
+    /// is occupied.
+    ///
+    /// Returns `None` only if the entry is vacant and the capacity of the cache is 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.entry(1).or_insert_with(|| String::from("a"));
+    /// cache.entry(1).or_insert_with(|| unreachable!());
+    /// assert_eq!(cache.get(&1).map(String::as_str), Some("a"));
+    /// ```
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> Option<&'a mut V> {
+        match self {
+            Entry::Occupied(entry) => Some(entry.into_mut()),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the fallible `f` if it is
+    /// vacant, and returns a mutable reference to the value in the entry. If `f` fails, its
+    /// error is returned and the cache is left unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache: LruCache<&str, u32> = LruCache::new(2);
+    ///
+    /// let res = cache.entry("apple").or_try_insert_with(|| "x".parse::<u32>());
+    /// assert!(res.is_err());
+    /// assert!(!cache.contains(&"apple"));
+    ///
+    /// let res = cache.entry("apple").or_try_insert_with(|| "3".parse::<u32>());
+    /// assert_eq!(res, Ok(Some(&mut 3)));
+    /// ```
+    pub fn or_try_insert_with<F, Er>(self, f: F) -> Result<Option<&'a mut V>, Er>
+    where
+        F: FnOnce() -> Result<V, Er>,
+    {
+        match self {
+            Entry::Occupied(entry) => Ok(Some(entry.into_mut())),
+            Entry::Vacant(entry) => Ok(entry.insert(f()?)),
+        }
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any potential inserts into
+    /// the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.entry("apple").and_modify(|v| *v += 1).or_insert(1);
+    /// cache.entry("apple").and_modify(|v| *v += 1).or_insert(1);
+    /// assert_eq!(cache.get(&"apple"), Some(&2));
+    /// ```
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+
+    /// Returns a reference to this entry's key.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+}
+
+impl<
+        'a,
+        K: Hash + Eq,
+        V,
+        S: BuildHasher,
+        E: Epoch,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator,
+    > with_alloc!(OccupiedEntry<'a, K, V, S, E>)
+{
+    pub(crate) fn new(
+        cache: &'a mut with_alloc!(LruCache<K, V, S, E>),
+        node: *mut LruEntry<K, V, E>,
+        hash: u64,
+    ) -> Self {
+        OccupiedEntry { cache, node, hash }
+    }
+
+    /// Returns a reference to this entry's key.
+    pub fn key(&self) -> &K {
+        unsafe { &*(*self.node).key.as_ptr() }
+    }
+
+    /// Returns a reference to the value in the entry.
+    pub fn get(&self) -> &V {
+        unsafe { &*(*self.node).val.as_ptr() }
+    }
+
+    /// Returns a mutable reference to the value in the entry. Use [`into_mut`] for a reference
+    /// that outlives the entry.
+    ///
+    /// [`into_mut`]: #method.into_mut
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe { &mut *(*self.node).val.as_mut_ptr() }
+    }
+
+    /// Converts the entry into a mutable reference to its value with the lifetime of the cache.
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe { &mut *(*self.node).val.as_mut_ptr() }
+    }
+
+    /// Moves the entry to the head of the LRU list, as if it had been accessed by `get`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{Entry, LruCache};
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// if let Entry::Occupied(mut entry) = cache.peek_entry(1) {
+    ///     entry.promote();
+    /// }
+    ///
+    /// assert_eq!(cache.peek_lru(), Some((&2, &"b")));
+    /// ```
+    pub fn promote(&mut self) {
+        let refresh = self.cache.epoch_policy == EpochPolicy::RefreshOnRead;
+        self.cache.touch(self.node, refresh);
+    }
+
+    /// Sets the value of the entry and returns the old value. The entry's position in the LRU
+    /// list is unchanged. This counts as a write, so the epoch is refreshed unless the
+    /// [`EpochPolicy`] is `NeverRefresh`, and like `put` the entry gets the default
+    /// time-to-live of the cache from now on.
+    ///
+    /// [`EpochPolicy`]: enum.EpochPolicy.html
+    pub fn insert(&mut self, mut value: V) -> V {
+        mem::swap(&mut value, self.get_mut());
+        let expires_at = self.cache.expiry(None);
+        unsafe { (*self.node).expires_at = expires_at };
+        if self.cache.epoch_policy != EpochPolicy::NeverRefresh {
+            let epoch = self.cache.cur_epoch;
+            self.cache.set_epoch(self.node, epoch);
+        }
+        let key = unsafe { &*(*self.node).key.as_ptr() };
+        self.cache.notify(key, &value, RemovalCause::Replaced);
+        value
+    }
+
+    /// Takes the value out of the cache and returns it.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Takes the key and value out of the cache and returns them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{Entry, LruCache};
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// if let Entry::Occupied(entry) = cache.entry(1) {
+    ///     assert_eq!(entry.remove_entry(), (1, "a"));
+    /// }
+    /// assert!(cache.is_empty());
+    /// ```
+    pub fn remove_entry(self) -> (K, V) {
+        self.cache
+            .remove_node(self.hash, self.node, RemovalCause::Explicit)
+    }
+}
+
+impl<
+        'a,
+        K: Hash + Eq,
+        V,
+        S: BuildHasher,
+        E: Epoch,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator,
+    > with_alloc!(VacantEntry<'a, K, V, S, E>)
+{
+    pub(crate) fn new(cache: &'a mut with_alloc!(LruCache<K, V, S, E>), key: K, hash: u64) -> Self {
+        VacantEntry { cache, key, hash }
+    }
+
+    /// Returns a reference to the key that would be used when inserting a value through this
+    /// entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes ownership of the key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts the value at the head of the LRU list, evicting the least recently used entry if
+    /// the cache is full, and returns a mutable reference to it.
+    ///
+    /// Returns `None`, dropping the value, only if the capacity of the cache is 0.
+    pub fn insert(self, value: V) -> Option<&'a mut V> {
+        self.push(value).0
+    }
+
+    /// Inserts the value like [`insert`], and also returns the least recently used entry that
+    /// had to be evicted to make room for it, or the entry the admission filter rejected, if
+    /// any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{Entry, LruCache};
+    /// let mut cache = LruCache::new(1);
+    ///
+    /// cache.put(1, "a");
+    /// if let Entry::Vacant(entry) = cache.entry(2) {
+    ///     let (value, evicted) = entry.push("b");
+    ///     assert_eq!(value, Some(&mut "b"));
+    ///     assert_eq!(evicted, Some((1, "a")));
+    /// }
+    /// ```
+    ///
+    /// [`insert`]: #method.insert
+    pub fn push(self, value: V) -> (Option<&'a mut V>, Option<(K, V)>) {
+        let VacantEntry { cache, key, hash } = self;
+        if cache.cap() == 0 {
+            return (None, None);
+        }
+
+        let expires_at = cache.expiry(None);
+        let (evicted, node) = cache.replace_or_create_node(key, value, 1, expires_at);
+        let node_ptr = cache.insert_node(hash, node);
+        let evicted = evicted.or(cache.enforce_capacity(node_ptr));
+        (Some(unsafe { &mut *(*node_ptr).val.as_mut_ptr() }), evicted)
+    }
+}
@@ -0,0 +1,167 @@
+// MIT License
+
+// Copyright (c) 2016 Jerome Froelich
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Estimates of the memory owned by a cache and by its keys and values.
+
+use std::mem;
+
+// The number of control bytes hashbrown appends to every table so that probes can read a full
+// group past its end; 16 with SSE2, which is the common case.
+const GROUP_WIDTH: usize = 16;
+
+/// The number of bytes a value owns on the heap, not counting the value itself.
+///
+/// `LruCache::deep_memory_usage` adds it up over all keys and values. It is implemented for
+/// the primitive types, which own nothing, and for the standard containers that own their
+/// contents.
+///
+/// # Example
+///
+/// ```
+/// use lru::HeapSize;
+///
+/// struct Block {
+///     id: u64,
+///     data: Vec<u8>,
+/// }
+///
+/// impl HeapSize for Block {
+///     fn heap_size(&self) -> usize {
+///         self.id.heap_size() + self.data.heap_size()
+///     }
+/// }
+///
+/// let block = Block { id: 1, data: vec![0; 4096] };
+/// assert_eq!(block.heap_size(), 4096);
+/// ```
+pub trait HeapSize {
+    /// Returns the number of bytes the value owns on the heap.
+    fn heap_size(&self) -> usize;
+}
+
+macro_rules! impl_heap_size_for_primitives {
+    ($($t:ty),*) => {
+        $(
+            impl HeapSize for $t {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+impl_heap_size_for_primitives!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    &'static str
+);
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * mem::size_of::<T>() + self.iter().map(T::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_size(&self) -> usize {
+        mem::size_of::<T>() + (**self).heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, T::heap_size)
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size()
+    }
+}
+
+// Estimates the bytes allocated by a hashbrown table of `T` with the given capacity: one slot
+// and one control byte per bucket, plus a trailing group of control bytes. Padding is left out.
+pub(crate) fn table_size<T>(capacity: usize) -> usize {
+    if capacity == 0 {
+        return 0;
+    }
+    // invert the load factor, which keeps one bucket free in small tables and an eighth of
+    // them in larger ones
+    let buckets = if capacity < 8 {
+        capacity + 1
+    } else {
+        capacity / 7 * 8
+    };
+    buckets * (mem::size_of::<T>() + 1) + GROUP_WIDTH
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{table_size, HeapSize};
+    use hashbrown::HashMap;
+
+    #[test]
+    fn test_heap_size_of_containers() {
+        assert_eq!(7u32.heap_size(), 0);
+        assert_eq!(String::with_capacity(10).heap_size(), 10);
+        assert_eq!(Vec::<u32>::with_capacity(4).heap_size(), 16);
+        assert_eq!(vec![String::from("abc"); 2].heap_size(), 2 * 24 + 6);
+        assert_eq!(Some(Box::new(1u64)).heap_size(), 8);
+        assert_eq!((String::from("ab"), 1u8).heap_size(), 2);
+    }
+
+    #[test]
+    fn test_table_size_follows_the_buckets() {
+        let mut map = HashMap::new();
+        assert_eq!(table_size::<(u64, u64)>(map.capacity()), 0);
+        map.insert(1u64, 1u64);
+        assert_eq!(table_size::<(u64, u64)>(map.capacity()), 4 * 17 + 16);
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.capacity(), 112);
+        assert_eq!(table_size::<(u64, u64)>(map.capacity()), 128 * 17 + 16);
+    }
+}
@@ -55,30 +55,106 @@
 //!         assert_eq!(*cache.get(&"banana").unwrap(), 6);
 //! }
 //! ```
+//!
+//! For caches shared between many threads, [`ShardedLruCache`] spreads entries over several
+//! independently locked `LruCache`s. For very large caches, [`SlabLruCache`] keeps its entries
+//! in one contiguous slab instead of allocating each of them. It has the original API of
+//! `LruCache`, but few of the features added to it since; charges and time-to-live, for
+//! example, are missing.
+//!
+//! With the `serde` feature, `LruCache` implements `Serialize` and `Deserialize`. A
+//! deserialized cache has the same capacity, epochs and recency order as the one that was
+//! serialized, except that expired entries are left out and entries that only fit because they
+//! are pinned are evicted. It is a plain LRU cache without an eviction listener, time-to-live,
+//! pins or segments. A clone of an `LruCache` keeps all of that, except for the eviction listener.
+//!
+//! The crate builds on stable Rust. The `allocator-api` feature adds a last type parameter
+//! `A` to `LruCache`, defaulting to the global allocator, and the `new_in`, `unbounded_in`,
+//! `with_hasher_in` and `unbounded_with_hasher_in` constructors, which store the entries in a
+//! custom allocator implementing the `Allocator` trait of the `allocator-api2` crate. Without
+//! the feature, neither the parameter nor the dependency exist. On nightly Rust, the `nightly`
+//! feature makes that trait the one from the standard library instead.
+//!
+//! [`ShardedLruCache`]: struct.ShardedLruCache.html
+//! [`SlabLruCache`]: struct.SlabLruCache.html
 
-#![feature(allocator_api)]
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
 
+#[cfg(feature = "allocator-api")]
+extern crate allocator_api2;
 extern crate hashbrown;
+#[cfg(feature = "serde")]
+extern crate serde;
 
 #[cfg(test)]
 extern crate scoped_threadpool;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
-use alloc::alloc::Global;
 use alloc::borrow::Borrow;
-use alloc::boxed::Box;
-use std::alloc::Allocator;
+#[cfg(feature = "allocator-api")]
+use allocator_api2::alloc::{Allocator, Global};
+use std::alloc::Layout;
+use std::collections::BTreeMap;
+use std::error::Error;
 use std::fmt;
 use std::hash::{BuildHasher, Hash, Hasher};
-use std::iter::FusedIterator;
+use std::iter::{FromIterator, FusedIterator};
 use std::marker::PhantomData;
 use std::mem;
 use std::ptr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use hashbrown::hash_map::RawEntryMut;
 use hashbrown::HashMap;
 
 extern crate alloc;
 
-type Epoch = u64;
+// Spells out a type that is generic over the allocator of the cache, which takes the allocator
+// parameter `A` last and only with the `allocator-api` feature, so that the impl blocks for
+// these types are written once for both builds.
+#[cfg(feature = "allocator-api")]
+macro_rules! with_alloc {
+    ($ty:ident<$($param:tt),*>) => { $ty<$($param),*, A> };
+}
+
+#[cfg(not(feature = "allocator-api"))]
+macro_rules! with_alloc {
+    ($ty:ident<$($param:tt),*>) => { $ty<$($param),*> };
+}
+
+mod clock;
+mod cursor;
+mod entry;
+mod heap_size;
+#[cfg(feature = "serde")]
+mod serialization;
+mod sharded;
+mod sketch;
+mod slab;
+#[cfg(feature = "stats")]
+mod stats;
+
+pub use clock::{Clock, MockClock, SystemClock};
+pub use cursor::CursorMut;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use heap_size::HeapSize;
+pub use sharded::ShardedLruCache;
+pub use slab::{SlabIntoIter, SlabIter, SlabIterMut, SlabLruCache};
+#[cfg(feature = "stats")]
+pub use stats::CacheStats;
+
+use sketch::FrequencySketch;
+
+/// The epoch of the entries of an `LruCache`, which is `u64` unless the cache is created with
+/// `with_epoch` or `unbounded_with_epoch`, which also take the epoch the cache starts in.
+/// Epochs are compared to decide which entries are older.
+///
+/// It is implemented for every type that is `Ord` and `Copy`.
+pub trait Epoch: Ord + Copy {}
+
+impl<T: Ord + Copy> Epoch for T {}
 
 // Struct used to hold a reference to a key
 #[doc(hidden)]
@@ -141,86 +217,357 @@ where
 
 // Struct used to hold a key value pair. Also contains references to previous and next entries
 // so we can maintain the entries in a linked list ordered by their use.
-struct LruEntry<K, V> {
+struct LruEntry<K, V, E> {
     key: mem::MaybeUninit<K>,
     val: mem::MaybeUninit<V>,
-    prev: *mut LruEntry<K, V>,
-    next: *mut LruEntry<K, V>,
-    epoch: Epoch,
+    prev: *mut LruEntry<K, V, E>,
+    next: *mut LruEntry<K, V, E>,
+    epoch: E,
+    charge: usize,
+    expires_at: Option<Instant>,
+    // how many times the entry is pinned; pinned entries are never chosen for eviction
+    pins: usize,
+    // the part of the list the entry sits in
+    segment: Segment,
+    // neighbours in the list of entries with the same epoch, kept only with an epoch index
+    epoch_prev: *mut LruEntry<K, V, E>,
+    epoch_next: *mut LruEntry<K, V, E>,
+}
+
+// The segments the list of a segmented cache is split into. Every entry of a plain LRU cache is
+// on probation.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Window,
+    Probation,
+    Protected,
 }
 
-impl<K, V> LruEntry<K, V> {
-    fn new(key: K, val: V, epoch: Epoch) -> Self {
+impl<K, V, E: Epoch> LruEntry<K, V, E> {
+    fn new(key: K, val: V, epoch: E, charge: usize, expires_at: Option<Instant>) -> Self {
         LruEntry {
             key: mem::MaybeUninit::new(key),
             val: mem::MaybeUninit::new(val),
             prev: ptr::null_mut(),
             next: ptr::null_mut(),
             epoch,
+            charge,
+            expires_at,
+            pins: 0,
+            segment: Segment::Probation,
+            epoch_prev: ptr::null_mut(),
+            epoch_next: ptr::null_mut(),
         }
     }
 
-    fn new_sigil() -> Self {
+    // The epoch of a sigil is never read, but has to be initialized all the same.
+    fn new_sigil(epoch: E) -> Self {
         LruEntry {
             key: mem::MaybeUninit::uninit(),
             val: mem::MaybeUninit::uninit(),
             prev: ptr::null_mut(),
             next: ptr::null_mut(),
-            epoch: 0,
+            epoch,
+            charge: 0,
+            expires_at: None,
+            pins: 0,
+            segment: Segment::Probation,
+            epoch_prev: ptr::null_mut(),
+            epoch_next: ptr::null_mut(),
         }
     }
 }
 
+// Moves the key and value out of a node that was taken out of the cache and frees the node.
+#[allow(clippy::boxed_local)]
+fn take_key_value<K, V, E, #[cfg(feature = "allocator-api")] A: Allocator>(
+    node: with_alloc!(Node<K, V, E>),
+) -> (K, V) {
+    unsafe { (node.key.assume_init_read(), node.val.assume_init_read()) }
+}
+
 pub type DefaultHasher = hashbrown::hash_map::DefaultHashBuilder;
 
+/// The error returned by the fallible insertion methods of an `LruCache` when the memory for an
+/// entry could not be allocated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The requested number of entries exceeds the capacity of the hash table.
+    CapacityOverflow,
+    /// The allocator failed to provide memory with the given layout.
+    AllocError {
+        /// The layout of the allocation that failed.
+        layout: Layout,
+    },
+}
+
+impl From<hashbrown::TryReserveError> for TryReserveError {
+    fn from(err: hashbrown::TryReserveError) -> Self {
+        match err {
+            hashbrown::TryReserveError::CapacityOverflow => TryReserveError::CapacityOverflow,
+            hashbrown::TryReserveError::AllocError { layout } => {
+                TryReserveError::AllocError { layout }
+            }
+        }
+    }
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryReserveError::CapacityOverflow => {
+                f.write_str("the requested number of entries exceeds the capacity of the cache")
+            }
+            TryReserveError::AllocError { layout } => write!(
+                f,
+                "failed to allocate {} bytes for the cache",
+                layout.size()
+            ),
+        }
+    }
+}
+
+impl Error for TryReserveError {}
+
+/// The reason an entry left an `LruCache`, as reported to its [`EvictionListener`].
+///
+/// [`EvictionListener`]: trait.EvictionListener.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RemovalCause {
+    /// The entry was evicted to make room for another entry.
+    Capacity,
+    /// The entry was evicted by `evict_by_epoch` or `pop_lru_by_epoch`.
+    Epoch,
+    /// The entry was removed by `pop`, `pop_entry`, `pop_lru`, `retain` or `extract_if`.
+    Explicit,
+    /// The value was overwritten by `put` or `push` on the same key.
+    Replaced,
+    /// The entry was evicted because the cache was resized.
+    Resize,
+    /// The entry was removed by `clear` or because the cache was dropped.
+    Clear,
+    /// The entry outlived its time-to-live and was reclaimed on access or by `evict_expired`.
+    Expired,
+    /// The entry left the admission window but was used less often than the entry it would
+    /// have replaced.
+    Rejected,
+}
+
+/// When the accesses to an entry of an `LruCache` set its epoch to the current one. An entry
+/// always takes the current epoch when it is inserted.
+///
+/// A cache whose entries keep their epoch when they move to the front of the list is no longer
+/// ordered by epoch, so `evict_by_epoch` should be paired with an epoch index there.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum EpochPolicy {
+    /// Reads such as `get` and writes such as `put` refresh the epoch. This is the default.
+    #[default]
+    RefreshOnRead,
+    /// Only writes refresh the epoch, so it records when the entry was last written. Besides
+    /// `put` and `push`, `get_mut` and `OccupiedEntry::insert` count as writes.
+    RefreshOnWrite,
+    /// The epoch records when the entry was inserted.
+    NeverRefresh,
+}
+
+/// How many entries of an `LruCache` have some epoch and what they are charged in total, as
+/// returned by `epoch_histogram`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct EpochUsage {
+    /// The number of entries with the epoch.
+    pub len: usize,
+    /// The sum of the charges of the entries with the epoch.
+    pub charge: usize,
+}
+
+// The entries with one epoch in the epoch index, as a list threaded through `epoch_prev` and
+// `epoch_next`.
+struct EpochBucket<K, V, E> {
+    first: *mut LruEntry<K, V, E>,
+}
+
+/// A callback that is invoked for every entry that leaves an `LruCache`.
+///
+/// It is implemented for all `FnMut(&K, &V, RemovalCause)` closures.
+pub trait EvictionListener<K, V> {
+    /// Called with the key and value of an entry right before it is removed from the cache.
+    fn on_evict(&mut self, key: &K, value: &V, cause: RemovalCause);
+}
+
+impl<K, V, F> EvictionListener<K, V> for F
+where
+    F: FnMut(&K, &V, RemovalCause),
+{
+    fn on_evict(&mut self, key: &K, value: &V, cause: RemovalCause) {
+        self(key, value, cause)
+    }
+}
+
+#[cfg(feature = "allocator-api")]
+type Node<K, V, E, A> = allocator_api2::boxed::Box<LruEntry<K, V, E>, A>;
+#[cfg(feature = "allocator-api")]
+type Map<K, V, S, E, A> = HashMap<KeyRef<K>, Node<K, V, E, A>, S, A>;
+
+#[cfg(not(feature = "allocator-api"))]
+type Node<K, V, E> = Box<LruEntry<K, V, E>>;
+#[cfg(not(feature = "allocator-api"))]
+type Map<K, V, S, E> = HashMap<KeyRef<K>, Node<K, V, E>, S>;
+
 /// An LRU Cache
-pub struct LruCache<K, V, S = DefaultHasher, A: Clone + Allocator = Global> {
-    map: HashMap<KeyRef<K>, Box<LruEntry<K, V>, A>, S, A>,
+///
+/// Every entry carries a charge, which is `1` unless it is inserted with `put_with_charge` or
+/// `push_with_charge`. The capacity bounds the total charge of all entries, so a cache whose
+/// entries all use the default charge holds at most `cap` entries.
+pub struct LruCache<
+    K,
+    V,
+    S = DefaultHasher,
+    E = u64,
+    #[cfg(feature = "allocator-api")] A: Clone + Allocator = Global,
+> {
+    map: with_alloc!(Map<K, V, S, E>),
     cap: usize,
+    usage: usize,
+    // the number of entries that are pinned
+    pinned: usize,
 
     // head and tail are sigil nodes to faciliate inserting entries
-    head: *mut LruEntry<K, V>,
-    tail: *mut LruEntry<K, V>,
+    head: *mut LruEntry<K, V, E>,
+    tail: *mut LruEntry<K, V, E>,
+
+    // in segmented mode, mid is a sigil node that separates the protected segment (between
+    // head and mid) from the probationary segment (between mid and tail); null otherwise
+    mid: *mut LruEntry<K, V, E>,
+    protected_ratio: f64,
+    protected_cap: usize,
+    protected_usage: usize,
+
+    // with an admission filter, win is a sigil node that separates the window segment
+    // (between head and win) from the protected segment; null otherwise
+    win: *mut LruEntry<K, V, E>,
+    window_ratio: f64,
+    window_cap: usize,
+    window_usage: usize,
+    sketch: Option<FrequencySketch>,
 
     /// used for epoch based eviction
-    cur_epoch: Epoch,
+    cur_epoch: E,
+    // the number and total charge of the entries with each epoch, kept with or without an index
+    epoch_usage: BTreeMap<E, EpochUsage>,
+    // if enabled, maps each epoch to the entries with that epoch
+    epoch_index: Option<BTreeMap<E, EpochBucket<K, V, E>>>,
+    epoch_policy: EpochPolicy,
+
+    listener: Option<Box<dyn EvictionListener<K, V> + Send>>,
+
+    // time-to-live given to entries that are inserted without one
+    ttl: Option<Duration>,
+    clock: Arc<dyn Clock>,
 
-    alloc: A,
+    #[cfg(feature = "stats")]
+    stats: CacheStats,
+
+    // nodes allocated ahead of time by `try_reserve`, chained through `next`
+    spare: *mut LruEntry<K, V, E>,
+    spare_len: usize,
 }
 
-impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A> {
+#[cfg(feature = "allocator-api")]
+impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, u64, A> {
+    /// Creates a new LRU Cache that holds at most `cap` items, uses the provided hash builder to
+    /// hash keys and stores its entries in the given allocator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// # extern crate allocator_api2;
+    /// # extern crate lru;
+    /// use allocator_api2::alloc::Global;
+    /// use lru::{LruCache, DefaultHasher};
+    ///
+    /// # fn main() {
+    /// let s = DefaultHasher::default();
+    /// let mut cache: LruCache<isize, &str, _, u64, Global> =
+    ///     LruCache::with_hasher_in(10, s, Global);
+    /// # }
+    /// ```
     pub fn with_hasher_in(cap: usize, hash_builder: S, alloc: A) -> Self {
-        LruCache::construct_in(
+        LruCache::construct(
             cap,
-            HashMap::with_capacity_and_hasher_in(cap, hash_builder, alloc.clone()),
-            alloc,
+            HashMap::with_capacity_and_hasher_in(cap, hash_builder, alloc),
+            0,
         )
     }
 
+    /// Creates a new LRU Cache that never automatically evicts items, uses the provided hash
+    /// builder to hash keys and stores its entries in the given allocator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// # extern crate allocator_api2;
+    /// # extern crate lru;
+    /// use allocator_api2::alloc::Global;
+    /// use lru::{LruCache, DefaultHasher};
+    ///
+    /// # fn main() {
+    /// let s = DefaultHasher::default();
+    /// let mut cache: LruCache<isize, &str, _, u64, Global> =
+    ///     LruCache::unbounded_with_hasher_in(s, Global);
+    /// # }
+    /// ```
     pub fn unbounded_with_hasher_in(hash_builder: S, alloc: A) -> Self {
-        LruCache::construct_in(
-            usize::MAX,
-            HashMap::with_hasher_in(hash_builder, alloc.clone()),
-            alloc,
-        )
+        LruCache::construct(usize::MAX, HashMap::with_hasher_in(hash_builder, alloc), 0)
     }
+}
 
-    /// Creates a new LRU Cache with the given capacity and allocator.
-    fn construct_in(
+impl<
+        K: Hash + Eq,
+        V,
+        S: BuildHasher,
+        E: Epoch,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator,
+    > with_alloc!(LruCache<K, V, S, E>)
+{
+    /// Creates a new LRU Cache with the given capacity that starts in the given epoch. The nodes
+    /// are allocated in the allocator of the map.
+    fn construct(
         cap: usize,
-        map: HashMap<KeyRef<K>, Box<LruEntry<K, V>, A>, S, A>,
-        alloc: A,
-    ) -> LruCache<K, V, S, A> {
-        // NB: The compiler warns that cache does not need to be marked as mutable if we
-        // declare it as such since we only mutate it inside the unsafe block.
-        let cache = LruCache {
+        map: with_alloc!(Map<K, V, S, E>),
+        epoch: E,
+    ) -> with_alloc!(LruCache<K, V, S, E>) {
+        let mut cache = LruCache {
             map,
             cap,
-            head: Box::into_raw(Box::new_in(LruEntry::new_sigil(), alloc.clone())),
-            tail: Box::into_raw(Box::new_in(LruEntry::new_sigil(), alloc.clone())),
-            cur_epoch: 0,
-            alloc,
+            usage: 0,
+            pinned: 0,
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+            mid: ptr::null_mut(),
+            protected_ratio: 0.0,
+            protected_cap: 0,
+            protected_usage: 0,
+            win: ptr::null_mut(),
+            window_ratio: 0.0,
+            window_cap: 0,
+            window_usage: 0,
+            sketch: None,
+            cur_epoch: epoch,
+            epoch_usage: BTreeMap::new(),
+            epoch_index: None,
+            epoch_policy: EpochPolicy::RefreshOnRead,
+            listener: None,
+            ttl: None,
+            clock: Arc::new(SystemClock),
+            #[cfg(feature = "stats")]
+            stats: CacheStats::default(),
+            spare: ptr::null_mut(),
+            spare_len: 0,
         };
+        cache.head = Node::into_raw(cache.new_node(LruEntry::new_sigil(epoch)));
+        cache.tail = Node::into_raw(cache.new_node(LruEntry::new_sigil(epoch)));
 
         unsafe {
             (*cache.head).next = cache.tail;
@@ -231,13 +578,96 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     }
 }
 
-impl<K: Hash + Eq, V, A: Clone + Allocator> LruCache<K, V, DefaultHasher, A> {
-    pub fn new_in(cap: usize, alloc: A) -> LruCache<K, V, DefaultHasher, A> {
-        LruCache::construct_in(cap, HashMap::with_capacity_in(cap, alloc.clone()), alloc)
+impl<K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> with_alloc!(LruCache<K, V, S, E>) {
+    // Allocates a node in the allocator of the map.
+    #[cfg(feature = "allocator-api")]
+    fn new_node(&self, entry: LruEntry<K, V, E>) -> Node<K, V, E, A> {
+        Node::new_in(entry, self.map.allocator().clone())
+    }
+
+    #[cfg(not(feature = "allocator-api"))]
+    fn new_node(&self, entry: LruEntry<K, V, E>) -> Node<K, V, E> {
+        Node::new(entry)
+    }
+
+    // Like `new_node`, but fails instead of aborting if the node cannot be allocated.
+    #[cfg(feature = "allocator-api")]
+    fn try_new_node(&self, entry: LruEntry<K, V, E>) -> Result<Node<K, V, E, A>, TryReserveError> {
+        Node::try_new_in(entry, self.map.allocator().clone()).map_err(|_| {
+            TryReserveError::AllocError {
+                layout: Layout::new::<LruEntry<K, V, E>>(),
+            }
+        })
+    }
+
+    #[cfg(not(feature = "allocator-api"))]
+    fn try_new_node(&self, entry: LruEntry<K, V, E>) -> Result<Node<K, V, E>, TryReserveError> {
+        // `Box::try_new` is not stable, so the node is allocated by hand
+        let layout = Layout::new::<LruEntry<K, V, E>>();
+        let node = unsafe { std::alloc::alloc(layout) } as *mut LruEntry<K, V, E>;
+        if node.is_null() {
+            return Err(TryReserveError::AllocError { layout });
+        }
+        unsafe {
+            node.write(entry);
+            Ok(Node::from_raw(node))
+        }
+    }
+
+    // Takes back a node that was turned into a raw pointer with `Node::into_raw`.
+    #[cfg(feature = "allocator-api")]
+    unsafe fn node_from_raw(&self, node: *mut LruEntry<K, V, E>) -> Node<K, V, E, A> {
+        Node::from_raw_in(node, self.map.allocator().clone())
+    }
+
+    #[cfg(not(feature = "allocator-api"))]
+    unsafe fn node_from_raw(&self, node: *mut LruEntry<K, V, E>) -> Node<K, V, E> {
+        Node::from_raw(node)
+    }
+}
+
+#[cfg(feature = "allocator-api")]
+impl<K: Hash + Eq, V, A: Clone + Allocator> LruCache<K, V, DefaultHasher, u64, A> {
+    /// Creates a new LRU Cache that holds at most `cap` items and stores its entries in the
+    /// given allocator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// # extern crate allocator_api2;
+    /// # extern crate lru;
+    /// use allocator_api2::alloc::Global;
+    /// use lru::LruCache;
+    ///
+    /// # fn main() {
+    /// let mut cache: LruCache<isize, &str, _, u64, Global> = LruCache::new_in(10, Global);
+    /// cache.put(1, "a");
+    /// assert_eq!(cache.get(&1), Some(&"a"));
+    /// # }
+    /// ```
+    pub fn new_in(cap: usize, alloc: A) -> Self {
+        LruCache::construct(cap, HashMap::with_capacity_in(cap, alloc), 0)
     }
 
-    pub fn unbounded_in(alloc: A) -> LruCache<K, V, DefaultHasher, A> {
-        LruCache::construct_in(usize::MAX, HashMap::new_in(alloc.clone()), alloc)
+    /// Creates a new LRU Cache that never automatically evicts items and stores its entries in
+    /// the given allocator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// # extern crate allocator_api2;
+    /// # extern crate lru;
+    /// use allocator_api2::alloc::Global;
+    /// use lru::LruCache;
+    ///
+    /// # fn main() {
+    /// let mut cache: LruCache<isize, &str, _, u64, Global> = LruCache::unbounded_in(Global);
+    /// # }
+    /// ```
+    pub fn unbounded_in(alloc: A) -> Self {
+        LruCache::construct(usize::MAX, HashMap::new_in(alloc), 0)
     }
 }
 
@@ -251,7 +681,7 @@ impl<K: Hash + Eq, V> LruCache<K, V> {
     /// let mut cache: LruCache<isize, &str> = LruCache::new(10);
     /// ```
     pub fn new(cap: usize) -> LruCache<K, V> {
-        LruCache::construct_in(cap, HashMap::with_capacity(cap), Global)
+        LruCache::construct(cap, HashMap::with_capacity(cap), 0)
     }
 
     /// Creates a new LRU Cache that never automatically evicts items.
@@ -263,7 +693,60 @@ impl<K: Hash + Eq, V> LruCache<K, V> {
     /// let mut cache: LruCache<isize, &str> = LruCache::unbounded();
     /// ```
     pub fn unbounded() -> LruCache<K, V> {
-        LruCache::construct_in(usize::MAX, HashMap::default(), Global)
+        LruCache::construct(usize::MAX, HashMap::default(), 0)
+    }
+
+    /// Creates a new LRU Cache whose entries have a total charge of at most `cap`. Unlike `new`,
+    /// no space is reserved up front since `cap` does not bound the number of entries.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache: LruCache<isize, Vec<u8>> = LruCache::weighted(64 << 20);
+    /// ```
+    pub fn weighted(cap: usize) -> LruCache<K, V> {
+        LruCache::construct(cap, HashMap::default(), 0)
+    }
+}
+
+impl<K: Hash + Eq, V, E: Epoch> LruCache<K, V, DefaultHasher, E> {
+    /// Creates a new LRU Cache that holds at most `cap` items and starts in the given epoch,
+    /// which may be of any [`Epoch`] type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    ///
+    /// // epochs made of a timestamp in seconds and a sequence number
+    /// let mut cache = LruCache::with_epoch(2, (1_700_000_000u64, 0u32));
+    /// cache.put(1, "a");
+    /// cache.update_epoch((1_700_000_000, 1));
+    /// cache.put(2, "b");
+    ///
+    /// cache.evict_by_epoch((1_700_000_000, 1));
+    /// assert_eq!(cache.epoch_of(&2), Some((1_700_000_000, 1)));
+    /// assert!(!cache.contains(&1));
+    /// ```
+    ///
+    /// [`Epoch`]: trait.Epoch.html
+    pub fn with_epoch(cap: usize, epoch: E) -> LruCache<K, V, DefaultHasher, E> {
+        LruCache::construct(cap, HashMap::with_capacity(cap), epoch)
+    }
+
+    /// Creates a new LRU Cache that never automatically evicts items and starts in the given
+    /// epoch.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let cache: LruCache<isize, &str, _, u32> = LruCache::unbounded_with_epoch(7);
+    /// assert_eq!(cache.current_epoch(), 7);
+    /// ```
+    pub fn unbounded_with_epoch(epoch: E) -> LruCache<K, V, DefaultHasher, E> {
+        LruCache::construct(usize::MAX, HashMap::default(), epoch)
     }
 }
 
@@ -280,11 +763,24 @@ impl<K: Hash + Eq, V, S: BuildHasher> LruCache<K, V, S> {
     /// let mut cache: LruCache<isize, &str> = LruCache::with_hasher(10, s);
     /// ```
     pub fn with_hasher(cap: usize, hash_builder: S) -> LruCache<K, V, S> {
-        LruCache::construct_in(
-            cap,
-            HashMap::with_capacity_and_hasher(cap, hash_builder),
-            Global,
-        )
+        LruCache::construct(cap, HashMap::with_capacity_and_hasher(cap, hash_builder), 0)
+    }
+
+    /// Creates a new LRU Cache whose entries have a total charge of at most `cap` and that uses
+    /// the provided hash builder to hash keys. See [`weighted`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{LruCache, DefaultHasher};
+    ///
+    /// let s = DefaultHasher::default();
+    /// let mut cache: LruCache<isize, Vec<u8>> = LruCache::weighted_with_hasher(64 << 20, s);
+    /// ```
+    ///
+    /// [`weighted`]: #method.weighted
+    pub fn weighted_with_hasher(cap: usize, hash_builder: S) -> LruCache<K, V, S> {
+        LruCache::construct(cap, HashMap::with_hasher(hash_builder), 0)
     }
 
     /// Creates a new LRU Cache that never automatically evicts items and
@@ -299,14 +795,24 @@ impl<K: Hash + Eq, V, S: BuildHasher> LruCache<K, V, S> {
     /// let mut cache: LruCache<isize, &str> = LruCache::unbounded_with_hasher(s);
     /// ```
     pub fn unbounded_with_hasher(hash_builder: S) -> LruCache<K, V, S> {
-        LruCache::construct_in(usize::MAX, HashMap::with_hasher(hash_builder), Global)
+        LruCache::construct(usize::MAX, HashMap::with_hasher(hash_builder), 0)
     }
 }
 
-impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A> {
+impl<
+        K: Hash + Eq,
+        V,
+        S: BuildHasher,
+        E: Epoch,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator,
+    > with_alloc!(LruCache<K, V, S, E>)
+{
     /// Puts a key-value pair into cache. If the key already exists in the cache, then it updates
     /// the key's value and returns the old value. Otherwise, `None` is returned.
     ///
+    /// A new entry is charged 1, while an existing entry keeps the charge it was given by
+    /// `put_with_charge`.
+    ///
     /// # Example
     ///
     /// ```
@@ -321,7 +827,95 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     /// assert_eq!(cache.get(&2), Some(&"beta"));
     /// ```
     pub fn put(&mut self, k: K, v: V) -> Option<V> {
-        self.capturing_put(k, v, false).map(|(_, v)| v)
+        self.capturing_put(k, v, None, None, None, false, false)
+            .map(|(_, v)| v)
+    }
+
+    /// Puts a key-value pair with the given charge into the cache. Least recently used entries
+    /// are evicted until the total charge fits within the capacity. If the key already exists in
+    /// the cache, then it updates the key's value and charge and returns the old value.
+    /// Otherwise, `None` is returned.
+    ///
+    /// An entry whose charge exceeds the capacity of the cache is never cached.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(10);
+    ///
+    /// assert_eq!(None, cache.put_with_charge(1, "a", 4));
+    /// assert_eq!(None, cache.put_with_charge(2, "b", 4));
+    /// assert_eq!(cache.usage(), 8);
+    ///
+    /// // Both entries have to go to make room for 3.
+    /// assert_eq!(None, cache.put_with_charge(3, "c", 9));
+    /// assert_eq!(cache.len(), 1);
+    /// assert_eq!(cache.usage(), 9);
+    ///
+    /// assert_eq!(Some("c"), cache.put_with_charge(3, "gamma", 2));
+    /// assert_eq!(cache.usage(), 2);
+    /// ```
+    pub fn put_with_charge(&mut self, k: K, v: V, charge: usize) -> Option<V> {
+        self.capturing_put(k, v, Some(charge), None, None, false, false)
+            .map(|(_, v)| v)
+    }
+
+    /// Puts a key-value pair into the cache that expires once `ttl` has passed, overriding the
+    /// cache-wide time-to-live. Otherwise behaves like `put`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{LruCache, MockClock};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let clock = Arc::new(MockClock::new());
+    /// let mut cache = LruCache::new(2);
+    /// cache.set_clock(clock.clone());
+    ///
+    /// cache.put_with_ttl(1, "a", Duration::from_secs(10));
+    /// cache.put(2, "b");
+    ///
+    /// clock.advance(Duration::from_secs(10));
+    /// assert_eq!(cache.get(&1), None);
+    /// assert_eq!(cache.get(&2), Some(&"b"));
+    /// ```
+    pub fn put_with_ttl(&mut self, k: K, v: V, ttl: Duration) -> Option<V> {
+        self.capturing_put(k, v, None, Some(ttl), None, false, false)
+            .map(|(_, v)| v)
+    }
+
+    /// Puts a key-value pair into the cache tagged with the given epoch instead of the current
+    /// one, which is useful when replaying data that belongs to an earlier epoch. Otherwise
+    /// behaves like `put`, so the entry becomes the most recently used one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `epoch` is ahead of the current epoch, as such an entry could not be evicted by
+    /// epoch consistently and a serialized cache holding it would not deserialize.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    /// cache.update_epoch(5);
+    ///
+    /// cache.put_with_epoch(1, "a", 3);
+    /// cache.put(2, "b");
+    ///
+    /// assert_eq!(cache.epoch_of(&1), Some(3));
+    /// assert_eq!(cache.peek_with_epoch(&2), Some((&"b", 5)));
+    /// ```
+    pub fn put_with_epoch(&mut self, k: K, v: V, epoch: E) -> Option<V> {
+        assert!(
+            epoch <= self.cur_epoch,
+            "an entry's epoch cannot be ahead of the current epoch"
+        );
+        self.capturing_put(k, v, None, None, Some(epoch), false, false)
+            .map(|(_, v)| v)
     }
 
     /// Pushes a key-value pair into the cache. If an entry with key `k` already exists in
@@ -348,72 +942,435 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     /// assert_eq!(cache.get(&3), Some(&"alpha"));
     /// ```
     pub fn push(&mut self, k: K, v: V) -> Option<(K, V)> {
-        self.capturing_put(k, v, true)
+        self.capturing_put(k, v, None, None, None, true, false)
+    }
+
+    /// Pushes a key-value pair with the given charge into the cache. Behaves like `push`, except
+    /// that several entries may have to be evicted to make room for the new one. In that case
+    /// only the least recently used of them is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(10);
+    ///
+    /// assert_eq!(None, cache.push_with_charge(1, "a", 4));
+    /// assert_eq!(None, cache.push_with_charge(2, "b", 4));
+    /// assert_eq!(Some((1, "a")), cache.push_with_charge(3, "c", 4));
+    /// assert_eq!(cache.usage(), 8);
+    /// ```
+    pub fn push_with_charge(&mut self, k: K, v: V, charge: usize) -> Option<(K, V)> {
+        self.capturing_put(k, v, Some(charge), None, None, true, false)
+    }
+
+    /// Puts a key-value pair into the cache at the tail of the LRU list instead of the head, so
+    /// that it is the next entry to be evicted unless it is read first. If the key already
+    /// exists in the cache, then it updates the key's value, leaves the entry where it is in
+    /// the list and returns the old value. Otherwise, `None` is returned.
+    ///
+    /// This keeps prefetched or scanned data from pushing out entries that are actually in use.
+    /// In a segmented cache the new entry joins the probationary segment directly, bypassing
+    /// the admission window.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// assert_eq!(None, cache.put_cold(2, "b"));
+    /// assert_eq!(cache.peek_lru(), Some((&2, &"b")));
+    ///
+    /// cache.put_cold(3, "c");
+    /// assert_eq!(cache.get(&1), Some(&"a"));
+    /// assert_eq!(cache.get(&2), None);
+    /// ```
+    pub fn put_cold(&mut self, k: K, v: V) -> Option<V> {
+        self.capturing_put(k, v, None, None, None, false, true)
+            .map(|(_, v)| v)
+    }
+
+    /// Pushes a key-value pair into the cache at the tail of the LRU list, like `put_cold`, and
+    /// returns the replaced or evicted entry, like `push`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// assert_eq!(Some((1, "a")), cache.push_cold(3, "c"));
+    /// assert_eq!(Some((3, "c")), cache.push_cold(3, "gamma"));
+    /// assert_eq!(cache.peek_lru(), Some((&3, &"gamma")));
+    /// ```
+    pub fn push_cold(&mut self, k: K, v: V) -> Option<(K, V)> {
+        self.capturing_put(k, v, None, None, None, true, true)
     }
 
     // Used internally by `put` and `push` to add a new entry to the lru.
     // Takes ownership of and returns entries replaced due to the cache's capacity
-    // when `capture` is true.
-    fn capturing_put(&mut self, k: K, mut v: V, capture: bool) -> Option<(K, V)> {
-        let node_ref = self.map.get_mut(&KeyRef { k: &k });
-
-        match node_ref {
-            Some(node_ref) => {
-                let node_ptr: *mut LruEntry<K, V> = &mut **node_ref;
+    // when `capture` is true. A `charge` of `None` keeps the charge of an existing entry and
+    // charges a new one 1. A `ttl` of `None` falls back to the cache-wide time-to-live, and
+    // an `epoch` of `None` to the current epoch. A `cold` entry is linked in at the tail of the
+    // list, and an existing one keeps its position.
+    #[allow(clippy::too_many_arguments)]
+    fn capturing_put(
+        &mut self,
+        k: K,
+        mut v: V,
+        charge: Option<usize>,
+        ttl: Option<Duration>,
+        epoch: Option<E>,
+        capture: bool,
+        cold: bool,
+    ) -> Option<(K, V)> {
+        let hash = self.hash_key(&k);
+        let expires_at = self.expiry(ttl);
+        self.record_access(hash);
+
+        match self.find_live_node(hash, &k) {
+            Some(node_ptr) => {
+                let charge = charge.unwrap_or(unsafe { (*node_ptr).charge });
+                // an entry that can never fit is dropped along with the value it replaces
+                if charge > self.cap {
+                    return self
+                        .remove_entry(&k, RemovalCause::Replaced)
+                        .map(|(_, old)| (k, old));
+                }
 
                 // if the key is already in the cache just update its value and move it to the
                 // front of the list
-                unsafe { mem::swap(&mut v, &mut (*(*node_ptr).val.as_mut_ptr()) as &mut V) }
-                self.detach(node_ptr);
-                self.attach(node_ptr);
+                unsafe {
+                    mem::swap(&mut v, &mut (*(*node_ptr).val.as_mut_ptr()) as &mut V);
+                    self.usage = self.usage - (*node_ptr).charge + charge;
+                    match (*node_ptr).segment {
+                        Segment::Window => {
+                            self.window_usage = self.window_usage - (*node_ptr).charge + charge
+                        }
+                        Segment::Protected => {
+                            self.protected_usage =
+                                self.protected_usage - (*node_ptr).charge + charge
+                        }
+                        Segment::Probation => {}
+                    }
+                    let usage = self.epoch_usage.get_mut(&(*node_ptr).epoch).unwrap();
+                    usage.charge = usage.charge - (*node_ptr).charge + charge;
+                    (*node_ptr).charge = charge;
+                    (*node_ptr).expires_at = expires_at;
+                }
+                self.notify(&k, &v, RemovalCause::Replaced);
+                let refresh = self.epoch_policy != EpochPolicy::NeverRefresh;
+                if !cold {
+                    self.touch(node_ptr, refresh);
+                } else if refresh {
+                    self.set_epoch(node_ptr, self.cur_epoch);
+                }
+                if let Some(epoch) = epoch {
+                    self.set_epoch(node_ptr, epoch);
+                }
+
+                // a larger charge may push other entries out
+                self.enforce_capacity(node_ptr);
                 Some((k, v))
             }
             None => {
-                // if the capacity is zero, do nothing
-                if self.cap() == 0 {
+                let charge = charge.unwrap_or(1);
+                // if the entry can never fit, do nothing
+                if charge > self.cap {
                     return None;
                 }
 
-                let (replaced, mut node) = self.replace_or_create_node(k, v);
-
-                let node_ptr: *mut LruEntry<K, V> = &mut *node;
-                self.attach(node_ptr);
-
-                let keyref = unsafe { (*node_ptr).key.as_ptr() };
-                self.map.insert(KeyRef { k: keyref }, node);
+                let (replaced, node) = self.replace_or_create_node(k, v, charge, expires_at);
+                let node_ptr = self.insert_node(hash, node);
+                if cold {
+                    self.move_to_tail(node_ptr);
+                }
+                if let Some(epoch) = epoch {
+                    self.set_epoch(node_ptr, epoch);
+                }
+                let replaced = replaced.or(self.enforce_capacity(node_ptr));
 
                 replaced.filter(|_| capture)
             }
         }
     }
 
-    // Used internally to swap out a node if the cache is full or to create a new node if space
-    // is available. Shared between `put`, `push`, and `get_or_insert`.
-    fn replace_or_create_node(&mut self, k: K, v: V) -> (Option<(K, V)>, Box<LruEntry<K, V>, A>) {
-        if self.len() == self.cap() {
-            // if the cache is full, remove the last entry so we can use it for the new key
-            let old_key = KeyRef {
-                k: unsafe { &(*(*(*self.tail).prev).key.as_ptr()) },
-            };
-            let mut old_node = self.map.remove(&old_key).unwrap();
+    // Used internally to link a node that is not yet in the cache into the map under the given
+    // hash and at the head of the list.
+    fn insert_node(
+        &mut self,
+        hash: u64,
+        mut node: with_alloc!(Node<K, V, E>),
+    ) -> *mut LruEntry<K, V, E> {
+        let node_ptr: *mut LruEntry<K, V, E> = &mut *node;
+        self.attach(node_ptr);
+
+        let keyref = KeyRef {
+            k: unsafe { (*node_ptr).key.as_ptr() },
+        };
+        self.usage += unsafe { (*node_ptr).charge };
+        self.record_insert();
+        match self.map.raw_entry_mut().from_hash(hash, |_| false) {
+            RawEntryMut::Vacant(entry) => {
+                entry.insert_hashed_nocheck(hash, keyref, node);
+            }
+            RawEntryMut::Occupied(_) => unreachable!(),
+        }
 
-            // read out the node's old key and value and then replace it
-            let replaced = unsafe { (old_node.key.assume_init(), old_node.val.assume_init()) };
+        node_ptr
+    }
 
-            old_node.key = mem::MaybeUninit::new(k);
-            old_node.val = mem::MaybeUninit::new(v);
+    // Used internally to unlink the given node, which must be stored in the map under `hash`,
+    // and hand back its key and value after reporting them to the eviction listener.
+    fn remove_node(
+        &mut self,
+        hash: u64,
+        node: *mut LruEntry<K, V, E>,
+        cause: RemovalCause,
+    ) -> (K, V) {
+        let old_node = match self
+            .map
+            .raw_entry_mut()
+            .from_hash(hash, |k| ptr::eq(k.k, unsafe { (*node).key.as_ptr() }))
+        {
+            RawEntryMut::Occupied(entry) => entry.remove(),
+            RawEntryMut::Vacant(_) => unreachable!(),
+        };
+        self.unlink(node);
 
-            let node_ptr: *mut LruEntry<K, V> = &mut *old_node;
-            self.detach(node_ptr);
+        let (key, val) = take_key_value(old_node);
+        self.notify(&key, &val, cause);
+        (key, val)
+    }
 
-            (Some(replaced), old_node)
-        } else {
-            // if the cache is not full allocate a new LruEntry
-            (
-                None,
-                Box::<_, A>::new_in(LruEntry::new(k, v, self.cur_epoch), self.alloc.clone()),
-            )
+    // Used internally to look up a node like `find_node`, except that an expired node is
+    // removed from the cache and reported as missing.
+    fn find_live_node<Q>(&mut self, hash: u64, k: &Q) -> Option<*mut LruEntry<K, V, E>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let node_ptr = self.find_node(hash, k)?;
+        if self.is_expired(node_ptr) {
+            self.remove_node(hash, node_ptr, RemovalCause::Expired);
+            return None;
         }
+        Some(node_ptr)
+    }
+
+    // Pinned entries do not expire until they are unpinned.
+    fn is_expired(&self, node: *const LruEntry<K, V, E>) -> bool {
+        if unsafe { (*node).pins } > 0 {
+            return false;
+        }
+        match unsafe { (*node).expires_at } {
+            Some(expires_at) => self.clock.now() >= expires_at,
+            None => false,
+        }
+    }
+
+    fn expiry(&self, ttl: Option<Duration>) -> Option<Instant> {
+        ttl.or(self.ttl).map(|ttl| self.clock.now() + ttl)
+    }
+
+    fn hash_key<Q: Hash + ?Sized>(&self, k: &Q) -> u64 {
+        self.map.hasher().hash_one(k)
+    }
+
+    fn find_node<Q>(&mut self, hash: u64, k: &Q) -> Option<*mut LruEntry<K, V, E>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        match self
+            .map
+            .raw_entry_mut()
+            .from_key_hashed_nocheck(hash, KeyWrapper::from_ref(k))
+        {
+            RawEntryMut::Occupied(entry) => Some(&mut **entry.into_mut()),
+            RawEntryMut::Vacant(_) => None,
+        }
+    }
+
+    // Used internally to swap out a node if the cache is full or to create a new node if space
+    // is available. Shared between `put`, `push`, and `get_or_insert`. The caller must make sure
+    // that `charge` does not exceed the capacity of the cache. With an admission filter a new
+    // node is always created, and the caller makes room with `enforce_capacity` once the node
+    // is inserted.
+    #[allow(clippy::type_complexity)]
+    fn replace_or_create_node(
+        &mut self,
+        k: K,
+        v: V,
+        charge: usize,
+        expires_at: Option<Instant>,
+    ) -> (Option<(K, V)>, with_alloc!(Node<K, V, E>)) {
+        // if the cache is full, remove the last entry so we can use it for the new key, unless
+        // every entry is pinned and the cache has to go over its capacity
+        let victim = if self.win.is_null() && self.usage + charge > self.cap {
+            self.remove_last()
+        } else {
+            None
+        };
+        if let Some(mut old_node) = victim {
+            // read out the node's old key and value and then replace it
+            let replaced = unsafe {
+                (
+                    old_node.key.assume_init_read(),
+                    old_node.val.assume_init_read(),
+                )
+            };
+            self.notify(&replaced.0, &replaced.1, RemovalCause::Capacity);
+
+            old_node.key = mem::MaybeUninit::new(k);
+            old_node.val = mem::MaybeUninit::new(v);
+            old_node.charge = charge;
+            old_node.expires_at = expires_at;
+
+            // heavier entries may need more than one victim
+            while self.usage + charge > self.cap {
+                if self.evict_lru(RemovalCause::Capacity).is_none() {
+                    break;
+                }
+            }
+
+            (Some(replaced), old_node)
+        } else {
+            // if the cache is not full allocate a new LruEntry, or take one set aside by
+            // `try_reserve`
+            let entry = LruEntry::new(k, v, self.cur_epoch, charge, expires_at);
+            match self.take_spare() {
+                Some(mut node) => {
+                    *node = entry;
+                    (None, node)
+                }
+                None => (None, self.new_node(entry)),
+            }
+        }
+    }
+
+    fn take_spare(&mut self) -> Option<with_alloc!(Node<K, V, E>)> {
+        if self.spare.is_null() {
+            return None;
+        }
+        let node = self.spare;
+        unsafe {
+            self.spare = (*node).next;
+            self.spare_len -= 1;
+            Some(self.node_from_raw(node))
+        }
+    }
+
+    /// Reserves memory for at least `additional` more entries, both in the hash table and for
+    /// the entries themselves, so that inserting them does not allocate. Returns an error
+    /// instead of aborting if the allocator fails.
+    ///
+    /// The epoch index, when enabled, still allocates as entries come and go.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(16);
+    ///
+    /// cache.try_reserve(16).unwrap();
+    /// for i in 0..16 {
+    ///     cache.put(i, i * 2);
+    /// }
+    /// assert_eq!(cache.len(), 16);
+    /// ```
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.map.try_reserve(additional)?;
+        while self.spare_len < additional {
+            let node = self.try_new_node(LruEntry::new_sigil(self.cur_epoch))?;
+            let node = Node::into_raw(node);
+            unsafe { (*node).next = self.spare };
+            self.spare = node;
+            self.spare_len += 1;
+        }
+        Ok(())
+    }
+
+    /// Like [`put`], but returns an error instead of aborting when the memory for a new entry
+    /// cannot be allocated. The key and value are dropped in that case.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// assert_eq!(cache.try_put(1, "a"), Ok(None));
+    /// assert_eq!(cache.try_put(1, "alpha"), Ok(Some("a")));
+    /// ```
+    ///
+    /// [`put`]: #method.put
+    pub fn try_put(&mut self, k: K, v: V) -> Result<Option<V>, TryReserveError> {
+        if !self.contains(&k) {
+            self.try_reserve_for_insert()?;
+        }
+        Ok(self.put(k, v))
+    }
+
+    /// Like [`push`], but returns an error instead of aborting when the memory for a new entry
+    /// cannot be allocated. The key and value are dropped in that case.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(1);
+    ///
+    /// assert_eq!(cache.try_push(1, "a"), Ok(None));
+    /// assert_eq!(cache.try_push(2, "b"), Ok(Some((1, "a"))));
+    /// ```
+    ///
+    /// [`push`]: #method.push
+    pub fn try_push(&mut self, k: K, v: V) -> Result<Option<(K, V)>, TryReserveError> {
+        if !self.contains(&k) {
+            self.try_reserve_for_insert()?;
+        }
+        Ok(self.push(k, v))
+    }
+
+    /// Like [`get_or_insert`], but returns an error instead of aborting when the memory for a
+    /// new entry cannot be allocated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// assert_eq!(cache.try_get_or_insert(1, || "a"), Ok(Some(&"a")));
+    /// assert_eq!(cache.try_get_or_insert(1, || "b"), Ok(Some(&"a")));
+    /// ```
+    ///
+    /// [`get_or_insert`]: #method.get_or_insert
+    pub fn try_get_or_insert<F>(&mut self, k: K, f: F) -> Result<Option<&V>, TryReserveError>
+    where
+        F: Fn() -> V,
+    {
+        if !self.contains(&k) {
+            self.try_reserve_for_insert()?;
+        }
+        Ok(self.get_or_insert(k, f))
+    }
+
+    // Reserves room for one more entry of charge 1, unless inserting it would not need any: an
+    // entry that can never fit is not inserted, and in a full cache without an admission window
+    // the new entry reuses the node and map slot of the entry it evicts.
+    fn try_reserve_for_insert(&mut self) -> Result<(), TryReserveError> {
+        let reuses_victim =
+            self.win.is_null() && self.usage >= self.cap && self.lru_node().is_some();
+        if self.cap == 0 || reuses_victim {
+            return Ok(());
+        }
+        self.try_reserve(1)
     }
 
     /// Returns a reference to the value of the key in the cache or `None` if it is not
@@ -439,21 +1396,48 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        if let Some(node) = self.map.get_mut(KeyWrapper::from_ref(k)) {
-            let node_ptr: *mut LruEntry<K, V> = &mut **node;
-
-            self.detach(node_ptr);
-            self.attach(node_ptr);
+        let refresh = self.epoch_policy == EpochPolicy::RefreshOnRead;
+        let node_ptr = self.lookup(k, refresh)?;
+        Some(unsafe { &(*(*node_ptr).val.as_ptr()) as &V })
+    }
 
-            Some(unsafe { &(*(*node_ptr).val.as_ptr()) as &V })
-        } else {
-            None
-        }
+    /// Returns a reference to the value of the key in the cache like `get`, moving the key to
+    /// the head of the LRU list but leaving its epoch unchanged whatever the [`EpochPolicy`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.update_epoch(1);
+    ///
+    /// assert_eq!(cache.get_keep_epoch(&1), Some(&"a"));
+    /// assert_eq!(cache.peek_lru(), Some((&2, &"b")));
+    ///
+    /// cache.set_epoch_index(true);
+    /// cache.evict_by_epoch(1);
+    /// assert!(cache.is_empty());
+    /// ```
+    ///
+    /// [`EpochPolicy`]: enum.EpochPolicy.html
+    pub fn get_keep_epoch<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node_ptr = self.lookup(k, false)?;
+        Some(unsafe { &(*(*node_ptr).val.as_ptr()) as &V })
     }
 
     /// Returns a mutable reference to the value of the key in the cache or `None` if it
     /// is not present in the cache. Moves the key to the head of the LRU list if it exists.
     ///
+    /// This counts as a write, so the epoch is refreshed unless the [`EpochPolicy`] is
+    /// `NeverRefresh`.
+    ///
     /// # Example
     ///
     /// ```
@@ -469,21 +1453,47 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     /// assert_eq!(cache.get_mut(&"banana"), Some(&mut 6));
     /// assert_eq!(cache.get_mut(&"pear"), Some(&mut 2));
     /// ```
+    ///
+    /// [`EpochPolicy`]: enum.EpochPolicy.html
     pub fn get_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        if let Some(node) = self.map.get_mut(KeyWrapper::from_ref(k)) {
-            let node_ptr: *mut LruEntry<K, V> = &mut **node;
+        let refresh = self.epoch_policy != EpochPolicy::NeverRefresh;
+        let node_ptr = self.lookup(k, refresh)?;
+        Some(unsafe { &mut (*(*node_ptr).val.as_mut_ptr()) as &mut V })
+    }
 
-            self.detach(node_ptr);
-            self.attach(node_ptr);
+    /// Returns a mutable reference to the value of the key in the cache like `get_mut`, moving
+    /// the key to the head of the LRU list but leaving its epoch unchanged whatever the
+    /// [`EpochPolicy`].
+    ///
+    /// [`EpochPolicy`]: enum.EpochPolicy.html
+    pub fn get_mut_keep_epoch<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node_ptr = self.lookup(k, false)?;
+        Some(unsafe { &mut (*(*node_ptr).val.as_mut_ptr()) as &mut V })
+    }
 
-            Some(unsafe { &mut (*(*node_ptr).val.as_mut_ptr()) as &mut V })
-        } else {
-            None
+    // Used internally by the `get` methods to find a live node, count the lookup and move the
+    // node to the head of the list.
+    fn lookup<Q>(&mut self, k: &Q, refresh_epoch: bool) -> Option<*mut LruEntry<K, V, E>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_key(k);
+        self.record_access(hash);
+        let node_ptr = self.find_live_node(hash, k);
+        self.record_lookup(node_ptr.is_some());
+        if let Some(node_ptr) = node_ptr {
+            self.touch(node_ptr, refresh_epoch);
         }
+        node_ptr
     }
 
     /// Returns a reference to the value of the key in the cache if it is
@@ -510,32 +1520,90 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     /// assert_eq!(cache.get_or_insert(1, ||"a"), Some(&"a"));
     /// assert_eq!(cache.get_or_insert(1, ||"b"), Some(&"a"));
     /// ```
+    #[allow(clippy::needless_lifetimes)]
     pub fn get_or_insert<'a, F>(&'a mut self, k: K, f: F) -> Option<&'a V>
     where
         F: Fn() -> V,
     {
-        if let Some(node) = self.map.get_mut(&KeyRef { k: &k }) {
-            let node_ptr: *mut LruEntry<K, V> = &mut **node;
-
-            self.detach(node_ptr);
-            self.attach(node_ptr);
+        let hash = self.hash_key(&k);
+        self.record_access(hash);
+        if let Some(node_ptr) = self.find_live_node(hash, &k) {
+            self.record_lookup(true);
+            self.touch(node_ptr, self.epoch_policy == EpochPolicy::RefreshOnRead);
 
             Some(unsafe { &(*(*node_ptr).val.as_ptr()) as &V })
         } else {
+            self.record_lookup(false);
             // If the capacity is 0 we do nothing,
             // this is the only circumstance that should return None
             if self.cap() == 0 {
                 return None;
             }
             let v = f();
-            let (_, mut node) = self.replace_or_create_node(k, v);
+            let expires_at = self.expiry(None);
+            let (_, node) = self.replace_or_create_node(k, v, 1, expires_at);
+            let node_ptr = self.insert_node(hash, node);
+            self.enforce_capacity(node_ptr);
+            Some(unsafe { &(*(*node_ptr).val.as_ptr()) as &V })
+        }
+    }
 
-            let node_ptr: *mut LruEntry<K, V> = &mut *node;
-            self.attach(node_ptr);
+    /// Gets the given key's entry in the cache for in-place manipulation. The key is hashed only
+    /// once, however the entry is used afterwards. If the key is present, it is moved to the
+    /// head of the LRU list like `get` does.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// for word in ["apple", "banana", "apple"] {
+    ///     *cache.entry(word).or_insert(0).unwrap() += 1;
+    /// }
+    ///
+    /// assert_eq!(cache.get(&"apple"), Some(&2));
+    /// assert_eq!(cache.get(&"banana"), Some(&1));
+    /// ```
+    pub fn entry(&mut self, k: K) -> with_alloc!(Entry<'_, K, V, S, E>) {
+        let hash = self.hash_key(&k);
+        self.record_access(hash);
+        match self.find_live_node(hash, &k) {
+            Some(node_ptr) => {
+                self.record_lookup(true);
+                self.touch(node_ptr, self.epoch_policy == EpochPolicy::RefreshOnRead);
+                Entry::Occupied(OccupiedEntry::new(self, node_ptr, hash))
+            }
+            None => {
+                self.record_lookup(false);
+                Entry::Vacant(VacantEntry::new(self, k, hash))
+            }
+        }
+    }
 
-            let keyref = unsafe { (*node_ptr).key.as_ptr() };
-            self.map.insert(KeyRef { k: keyref }, node);
-            Some(unsafe { &(*(*node_ptr).val.as_ptr()) as &V })
+    /// Gets the given key's entry in the cache for in-place manipulation. Unlike `entry`,
+    /// `peek_entry` does not update the LRU list, so an occupied entry keeps its position
+    /// unless it is moved explicitly with [`OccupiedEntry::promote`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.peek_entry(1).and_modify(|v| *v = "alpha");
+    ///
+    /// assert_eq!(cache.peek_lru(), Some((&1, &"alpha")));
+    /// ```
+    ///
+    /// [`OccupiedEntry::promote`]: struct.OccupiedEntry.html#method.promote
+    pub fn peek_entry(&mut self, k: K) -> with_alloc!(Entry<'_, K, V, S, E>) {
+        let hash = self.hash_key(&k);
+        match self.find_live_node(hash, &k) {
+            Some(node_ptr) => Entry::Occupied(OccupiedEntry::new(self, node_ptr, hash)),
+            None => Entry::Vacant(VacantEntry::new(self, k, hash)),
         }
     }
 
@@ -555,6 +1623,7 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     /// assert_eq!(cache.peek(&1), Some(&"a"));
     /// assert_eq!(cache.peek(&2), Some(&"b"));
     /// ```
+    #[allow(clippy::explicit_auto_deref)]
     pub fn peek<'a, Q>(&'a self, k: &Q) -> Option<&'a V>
     where
         K: Borrow<Q>,
@@ -562,12 +1631,63 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     {
         self.map
             .get(KeyWrapper::from_ref(k))
+            .filter(|node| !self.is_expired(&***node))
             .map(|node| unsafe { &(*(*node).val.as_ptr()) as &V })
     }
 
+    /// Returns a reference to the value corresponding to the key in the cache along with the
+    /// entry's epoch, or `None` if it is not present in the cache. Like `peek`, this does not
+    /// update the LRU list.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.update_epoch(1);
+    ///
+    /// assert_eq!(cache.peek_with_epoch(&1), Some((&"a", 0)));
+    /// assert_eq!(cache.peek_with_epoch(&2), None);
+    /// ```
+    pub fn peek_with_epoch<'a, Q>(&'a self, k: &Q) -> Option<(&'a V, E)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map
+            .get(KeyWrapper::from_ref(k))
+            .filter(|node| !self.is_expired(&***node))
+            .map(|node| (unsafe { &(*node.val.as_ptr()) as &V }, node.epoch))
+    }
+
+    /// Returns the epoch of the key's entry, or `None` if it is not present in the cache. Like
+    /// `peek`, this does not update the LRU list.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.update_epoch(1);
+    /// cache.put(1, "a");
+    ///
+    /// assert_eq!(cache.epoch_of(&1), Some(1));
+    /// assert_eq!(cache.epoch_of(&2), None);
+    /// ```
+    pub fn epoch_of<Q>(&self, k: &Q) -> Option<E>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek_with_epoch(k).map(|(_, epoch)| epoch)
+    }
+
     /// Returns a mutable reference to the value corresponding to the key in the cache or `None`
     /// if it is not present in the cache. Unlike `get_mut`, `peek_mut` does not update the LRU
-    /// list so the key's position will be unchanged.
+    /// list so the key's position will be unchanged. The epoch is left unchanged as well.
     ///
     /// # Example
     ///
@@ -588,13 +1708,20 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     {
         match self.map.get_mut(KeyWrapper::from_ref(k)) {
             None => None,
-            Some(node) => Some(unsafe { &mut (*(*node).val.as_mut_ptr()) as &mut V }),
+            Some(node) => {
+                let node_ptr: *mut LruEntry<K, V, E> = &mut **node;
+                if self.is_expired(node_ptr) {
+                    None
+                } else {
+                    Some(unsafe { &mut (*(*node_ptr).val.as_mut_ptr()) as &mut V })
+                }
+            }
         }
     }
 
     /// Returns the value corresponding to the least recently used item or `None` if the
     /// cache is empty. Like `peek`, `peek_lru` does not update the LRU list so the item's
-    /// position will be unchanged.
+    /// position will be unchanged. Pinned and expired items are passed over.
     ///
     /// # Example
     ///
@@ -608,13 +1735,13 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     /// assert_eq!(cache.peek_lru(), Some((&1, &"a")));
     /// ```
     pub fn peek_lru<'a>(&'_ self) -> Option<(&'a K, &'a V)> {
-        if self.is_empty() {
-            return None;
+        let mut node = self.lru_node()?;
+        while self.is_expired(node) {
+            node = self.lru_node_before(node)?;
         }
 
         let (key, val);
         unsafe {
-            let node = (*self.tail).prev;
             key = &(*(*node).key.as_ptr()) as &K;
             val = &(*(*node).val.as_ptr()) as &V;
         }
@@ -644,11 +1771,11 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.map.contains_key(KeyWrapper::from_ref(k))
+        self.peek(k).is_some()
     }
 
     /// Removes and returns the value corresponding to the key from the cache or
-    /// `None` if it does not exist.
+    /// `None` if it does not exist. An expired entry is reclaimed and `None` is returned.
     ///
     /// # Example
     ///
@@ -668,21 +1795,11 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        match self.map.remove(KeyWrapper::from_ref(k)) {
-            None => None,
-            Some(mut old_node) => {
-                unsafe {
-                    ptr::drop_in_place(old_node.key.as_mut_ptr());
-                }
-                let node_ptr: *mut LruEntry<K, V> = &mut *old_node;
-                self.detach(node_ptr);
-                unsafe { Some(old_node.val.assume_init()) }
-            }
-        }
+        self.pop_entry(k).map(|(_, v)| v)
     }
 
     /// Removes and returns the key and the value corresponding to the key from the cache or
-    /// `None` if it does not exist.
+    /// `None` if it does not exist. An expired entry is reclaimed and `None` is returned.
     ///
     /// # Example
     ///
@@ -700,6 +1817,18 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     /// assert_eq!(cache.len(), 0);
     /// ```
     pub fn pop_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_key(k);
+        let node = self.find_live_node(hash, k)?;
+        Some(self.remove_node(hash, node, RemovalCause::Explicit))
+    }
+
+    // Used internally to remove the entry with the given key, reporting it to the eviction
+    // listener with the given cause.
+    fn remove_entry<Q>(&mut self, k: &Q, cause: RemovalCause) -> Option<(K, V)>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
@@ -707,15 +1836,18 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
         match self.map.remove(KeyWrapper::from_ref(k)) {
             None => None,
             Some(mut old_node) => {
-                let node_ptr: *mut LruEntry<K, V> = &mut *old_node;
-                self.detach(node_ptr);
-                unsafe { Some((old_node.key.assume_init(), old_node.val.assume_init())) }
+                let node_ptr: *mut LruEntry<K, V, E> = &mut *old_node;
+                self.unlink(node_ptr);
+                let (key, val) = take_key_value(old_node);
+                self.notify(&key, &val, cause);
+                Some((key, val))
             }
         }
     }
 
     /// Removes and returns the key and value corresponding to the least recently
-    /// used item or `None` if the cache is empty.
+    /// used item or `None` if the cache is empty. Pinned items are passed over, and expired
+    /// items met on the way are reclaimed.
     ///
     /// # Example
     ///
@@ -734,167 +1866,303 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     /// assert_eq!(cache.len(), 0);
     /// ```
     pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        loop {
+            let node = self.lru_node()?;
+            if !self.is_expired(node) {
+                return Some(self.evict_node(node, RemovalCause::Explicit));
+            }
+            self.evict_node(node, RemovalCause::Expired);
+        }
+    }
+
+    // Used internally to remove the least recently used entry, reporting it to the eviction
+    // listener with the given cause.
+    fn evict_lru(&mut self, cause: RemovalCause) -> Option<(K, V)> {
         let node = self.remove_last()?;
-        // N.B.: Can't destructure directly because of https://github.com/rust-lang/rust/issues/28536
-        let node = *node;
-        let LruEntry { key, val, .. } = node;
-        unsafe { Some((key.assume_init(), val.assume_init())) }
+        let (key, val) = take_key_value(node);
+        self.notify(&key, &val, cause);
+        Some((key, val))
     }
 
-    /// Returns the number of key-value pairs that are currently in the the cache.
+    /// Moves the entry of the given key to the head of the LRU list, as `get` would, without
+    /// returning its value, and returns whether the key is in the cache. The epoch is refreshed
+    /// according to the [`EpochPolicy`]. Unlike `get`, this is not counted as a lookup.
     ///
     /// # Example
     ///
     /// ```
     /// use lru::LruCache;
     /// let mut cache = LruCache::new(2);
-    /// assert_eq!(cache.len(), 0);
     ///
     /// cache.put(1, "a");
-    /// assert_eq!(cache.len(), 1);
-    ///
     /// cache.put(2, "b");
-    /// assert_eq!(cache.len(), 2);
+    /// assert!(cache.promote(&1));
+    /// assert!(!cache.promote(&3));
     ///
     /// cache.put(3, "c");
-    /// assert_eq!(cache.len(), 2);
+    /// assert_eq!(cache.get(&2), None);
     /// ```
-    pub fn len(&self) -> usize {
-        self.map.len()
+    ///
+    /// [`EpochPolicy`]: enum.EpochPolicy.html
+    pub fn promote<Q>(&mut self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_key(k);
+        match self.find_live_node(hash, k) {
+            Some(node) => {
+                let refresh = self.epoch_policy == EpochPolicy::RefreshOnRead;
+                self.touch(node, refresh);
+                true
+            }
+            None => false,
+        }
     }
 
-    /// Returns a bool indicating whether the cache is empty or not.
+    /// Moves the entry of the given key to the tail of the LRU list, so that it is the next one
+    /// to be evicted unless it is pinned, and returns whether the key is in the cache. In a
+    /// segmented cache the entry joins the probationary segment. Its epoch is unchanged.
     ///
     /// # Example
     ///
     /// ```
     /// use lru::LruCache;
     /// let mut cache = LruCache::new(2);
-    /// assert!(cache.is_empty());
     ///
     /// cache.put(1, "a");
-    /// assert!(!cache.is_empty());
+    /// cache.put(2, "b");
+    /// assert!(cache.demote(&2));
+    ///
+    /// cache.put(3, "c");
+    /// assert_eq!(cache.get(&2), None);
+    /// assert_eq!(cache.get(&1), Some(&"a"));
     /// ```
-    pub fn is_empty(&self) -> bool {
-        self.map.len() == 0
+    pub fn demote<Q>(&mut self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_key(k);
+        match self.find_live_node(hash, k) {
+            Some(node) => {
+                self.move_to_tail(node);
+                true
+            }
+            None => false,
+        }
     }
 
-    /// Returns the maximum number of key-value pairs the cache can hold.
+    /// Moves the entry of the key `k` right in front of the entry of `anchor`, so that it is
+    /// the next more recently used entry, and returns whether both keys are in the cache. The
+    /// entry joins the segment of `anchor` and keeps its epoch.
+    /// A segment that ends up over its share passes its least recently used entries on, as
+    /// after an insert.
     ///
     /// # Example
     ///
     /// ```
     /// use lru::LruCache;
-    /// let mut cache: LruCache<isize, &str> = LruCache::new(2);
-    /// assert_eq!(cache.cap(), 2);
-    /// ```
-    pub fn cap(&self) -> usize {
-        self.cap
+    /// let mut cache = LruCache::new(3);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.put(3, "c");
+    /// assert!(cache.move_before(&1, &2));
+    ///
+    /// assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![3, 1, 2]);
+    /// ```
+    pub fn move_before<Q>(&mut self, k: &Q, anchor: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.reposition(k, anchor, false)
     }
 
-    /// Resizes the cache. If the new capacity is smaller than the size of the current
-    /// cache any entries past the new capacity are discarded.
+    /// Moves the entry of the key `k` right behind the entry of `anchor`, so that it is the
+    /// next less recently used entry, and returns whether both keys are in the cache. The entry
+    /// joins the segment of `anchor` and keeps its epoch.
+    /// A segment that ends up over its share passes its least recently used entries on, as
+    /// after an insert.
     ///
     /// # Example
     ///
     /// ```
     /// use lru::LruCache;
-    /// let mut cache: LruCache<isize, &str> = LruCache::new(2);
+    /// let mut cache = LruCache::new(3);
     ///
     /// cache.put(1, "a");
     /// cache.put(2, "b");
-    /// cache.resize(4);
     /// cache.put(3, "c");
-    /// cache.put(4, "d");
+    /// assert!(cache.move_after(&3, &2));
     ///
-    /// assert_eq!(cache.len(), 4);
-    /// assert_eq!(cache.get(&1), Some(&"a"));
-    /// assert_eq!(cache.get(&2), Some(&"b"));
-    /// assert_eq!(cache.get(&3), Some(&"c"));
-    /// assert_eq!(cache.get(&4), Some(&"d"));
+    /// assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![2, 3, 1]);
     /// ```
-    pub fn resize(&mut self, cap: usize) {
-        // return early if capacity doesn't change
-        if cap == self.cap {
-            return;
-        }
-
-        while self.map.len() > cap {
-            self.pop_lru();
-        }
-        self.map.shrink_to_fit();
-
-        self.cap = cap;
-    }
-
-    /// Update the current epoch. The given epoch should be greater than the current epoch.
-    pub fn update_epoch(&mut self, epoch: Epoch) {
-        assert!(epoch > self.cur_epoch);
-        self.cur_epoch = epoch;
-    }
-
-    pub fn current_epoch(&self) -> Epoch {
-        self.cur_epoch
+    pub fn move_after<Q>(&mut self, k: &Q, anchor: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.reposition(k, anchor, true)
     }
 
-    /// Update the current epoch. The given epoch should be greater than the current epoch.
-    pub fn pop_lru_by_epoch(&mut self, epoch: Epoch) -> Option<(K, V, Epoch)> {
-        let node = unsafe { (*self.tail).prev };
-        let node_epoch = unsafe { (*node).epoch };
-        if node_epoch < epoch {
-            if node != self.head {
-                let old_key = KeyRef {
-                    k: unsafe { &(*(*(*self.tail).prev).key.as_ptr()) },
-                };
-                let mut old_node = self.map.remove(&old_key).unwrap();
-                let node_ptr: *mut LruEntry<K, V> = &mut *old_node;
-                self.detach(node_ptr);
-                let LruEntry { key, val, .. } = *old_node;
-                unsafe { Some((key.assume_init(), val.assume_init(), node_epoch)) }
+    fn reposition<Q>(&mut self, k: &Q, anchor: &Q, after: bool) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = match self.find_live_node(self.hash_key(k), k) {
+            Some(node) => node,
+            None => return false,
+        };
+        let anchor = match self.find_live_node(self.hash_key(anchor), anchor) {
+            Some(anchor) => anchor,
+            None => return false,
+        };
+        if node != anchor {
+            let segment = unsafe { (*anchor).segment };
+            self.detach(node);
+            let prev = if after {
+                anchor
             } else {
-                None
+                unsafe { (*anchor).prev }
+            };
+            self.link_after(node, prev);
+            unsafe {
+                (*node).segment = segment;
+                match segment {
+                    Segment::Window => self.window_usage += (*node).charge,
+                    Segment::Protected => self.protected_usage += (*node).charge,
+                    Segment::Probation => {}
+                }
             }
-        } else {
-            None
+            self.demote_overflow();
+            self.enforce_capacity(ptr::null_mut());
         }
+        true
     }
 
-    /// Evict entries by given epoch
-    pub fn evict_by_epoch(&mut self, epoch: Epoch) {
-        loop {
-            if self.is_empty() {
-                break;
-            }
-
-            let node = unsafe { (*self.tail).prev };
-            let node_epoch = unsafe { (*node).epoch };
-            if node_epoch < epoch {
-                let old_key = KeyRef {
-                    k: unsafe { &(*(*node).key.as_ptr()) },
-                };
-                let mut old_node = self.map.remove(&old_key).unwrap();
+    /// Pins the entry of the given key, so that it is never chosen for eviction or expired, and
+    /// returns whether the key is in the cache. Does not update the LRU list, so the entry keeps
+    /// its position and takes part in eviction again once it is unpinned.
+    ///
+    /// An entry can be pinned several times and stays pinned until it is unpinned as often. It
+    /// can still be removed explicitly, for example with `pop` or `clear`. When every entry that
+    /// could make room is pinned, the cache goes over its capacity, see [`is_overcommitted`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// assert!(cache.pin(&1));
+    ///
+    /// cache.put(3, "c");
+    /// assert!(cache.contains(&1));
+    /// assert!(!cache.contains(&2));
+    ///
+    /// assert_eq!(cache.pop_lru(), Some((3, "c")));
+    /// assert_eq!(cache.pop_lru(), None);
+    /// ```
+    ///
+    /// [`is_overcommitted`]: #method.is_overcommitted
+    pub fn pin<Q>(&mut self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_key(k);
+        match self.find_live_node(hash, k) {
+            Some(node) => {
                 unsafe {
-                    ptr::drop_in_place(old_node.key.as_mut_ptr());
-                    ptr::drop_in_place(old_node.val.as_mut_ptr());
+                    if (*node).pins == 0 {
+                        self.pinned += 1;
+                    }
+                    (*node).pins += 1;
                 }
-                let node_ptr: *mut LruEntry<K, V> = &mut *old_node;
+                true
+            }
+            None => false,
+        }
+    }
 
-                self.detach(node_ptr);
-            } else {
-                break;
+    /// Releases a pin taken with [`pin`] and returns whether the key was pinned. Once the last
+    /// pin of an overcommitted cache is gone, entries are evicted until it fits its capacity
+    /// again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(1);
+    ///
+    /// cache.put(1, "a");
+    /// cache.pin(&1);
+    /// cache.put(2, "b");
+    /// assert!(cache.is_overcommitted());
+    ///
+    /// assert!(cache.unpin(&1));
+    /// assert!(!cache.unpin(&1));
+    /// assert!(!cache.is_overcommitted());
+    /// assert_eq!(cache.len(), 1);
+    /// ```
+    ///
+    /// [`pin`]: #method.pin
+    pub fn unpin<Q>(&mut self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_key(k);
+        let node = match self.find_node(hash, k) {
+            Some(node) if unsafe { (*node).pins } > 0 => node,
+            _ => return false,
+        };
+        unsafe {
+            (*node).pins -= 1;
+            if (*node).pins == 0 {
+                self.pinned -= 1;
+                self.enforce_capacity(ptr::null_mut());
             }
         }
+        true
+    }
 
-        self.map.shrink_to_fit();
+    /// Returns whether the entry of the given key is pinned.
+    pub fn is_pinned<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map
+            .get(KeyWrapper::from_ref(k))
+            .is_some_and(|node| node.pins > 0)
+    }
+
+    /// Returns the number of entries that are pinned.
+    pub fn pinned_len(&self) -> usize {
+        self.pinned
     }
 
-    /// Clears the contents of the cache.
+    /// Returns whether the entries are charged more than the capacity of the cache, which only
+    /// happens when every entry that could have been evicted to make room is pinned.
+    pub fn is_overcommitted(&self) -> bool {
+        self.usage > self.cap
+    }
+
+    /// Returns the number of key-value pairs that are currently in the the cache.
+    /// Entries that have expired are counted until they are looked up or reclaimed by
+    /// [`evict_expired`].
     ///
     /// # Example
     ///
     /// ```
     /// use lru::LruCache;
-    /// let mut cache: LruCache<isize, &str> = LruCache::new(2);
+    /// let mut cache = LruCache::new(2);
     /// assert_eq!(cache.len(), 0);
     ///
     /// cache.put(1, "a");
@@ -903,718 +2171,3435 @@ impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> LruCache<K, V, S, A>
     /// cache.put(2, "b");
     /// assert_eq!(cache.len(), 2);
     ///
-    /// cache.clear();
-    /// assert_eq!(cache.len(), 0);
+    /// cache.put(3, "c");
+    /// assert_eq!(cache.len(), 2);
     /// ```
-    pub fn clear(&mut self) {
-        while self.pop_lru().is_some() {}
+    ///
+    /// [`evict_expired`]: #method.evict_expired
+    pub fn len(&self) -> usize {
+        self.map.len()
     }
 
-    /// An iterator visiting all entries in most-recently used order. The iterator element type is
-    /// `(&K, &V)`.
+    /// Returns a bool indicating whether the cache is empty or not.
     ///
-    /// # Examples
+    /// # Example
     ///
     /// ```
     /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    /// assert!(cache.is_empty());
     ///
-    /// let mut cache = LruCache::new(3);
-    /// cache.put("a", 1);
-    /// cache.put("b", 2);
-    /// cache.put("c", 3);
-    ///
-    /// for (key, val) in cache.iter() {
-    ///     println!("key: {} val: {}", key, val);
-    /// }
+    /// cache.put(1, "a");
+    /// assert!(!cache.is_empty());
     /// ```
-    pub fn iter(&self) -> Iter<'_, K, V> {
-        Iter {
-            len: self.len(),
-            ptr: unsafe { (*self.head).next },
-            end: unsafe { (*self.tail).prev },
-            phantom: PhantomData,
-        }
+    pub fn is_empty(&self) -> bool {
+        self.map.len() == 0
     }
 
-    /// An iterator visiting all entries in most-recently-used order, giving a mutable reference on
-    /// V.  The iterator element type is `(&K, &mut V)`.
+    /// Returns the maximum total charge of the key-value pairs the cache can hold. As entries
+    /// are charged `1` by default, this is the maximum number of entries unless charges are
+    /// given explicitly.
     ///
-    /// # Examples
+    /// # Example
     ///
     /// ```
     /// use lru::LruCache;
+    /// let mut cache: LruCache<isize, &str> = LruCache::new(2);
+    /// assert_eq!(cache.cap(), 2);
+    /// ```
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    /// Returns the total charge of the key-value pairs that are currently in the cache.
     ///
-    /// struct HddBlock {
-    ///     dirty: bool,
-    ///     data: [u8; 512]
-    /// }
+    /// # Example
     ///
-    /// let mut cache = LruCache::new(3);
-    /// cache.put(0, HddBlock { dirty: false, data: [0x00; 512]});
-    /// cache.put(1, HddBlock { dirty: true,  data: [0x55; 512]});
-    /// cache.put(2, HddBlock { dirty: true,  data: [0x77; 512]});
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(10);
+    /// assert_eq!(cache.usage(), 0);
     ///
-    /// // write dirty blocks to disk.
-    /// for (block_id, block) in cache.iter_mut() {
-    ///     if block.dirty {
-    ///         // write block to disk
-    ///         block.dirty = false
-    ///     }
-    /// }
+    /// cache.put(1, "a");
+    /// cache.put_with_charge(2, "b", 5);
+    /// assert_eq!(cache.usage(), 6);
     /// ```
-    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
-        IterMut {
-            len: self.len(),
-            ptr: unsafe { (*self.head).next },
-            end: unsafe { (*self.tail).prev },
-            phantom: PhantomData,
-        }
+    pub fn usage(&self) -> usize {
+        self.usage
     }
 
-    fn remove_last(&mut self) -> Option<Box<LruEntry<K, V>, A>> {
-        let prev;
-        unsafe { prev = (*self.tail).prev }
-        if prev != self.head {
-            let old_key = KeyRef {
-                k: unsafe { &(*(*(*self.tail).prev).key.as_ptr()) },
-            };
-            let mut old_node = self.map.remove(&old_key).unwrap();
-            let node_ptr: *mut LruEntry<K, V> = &mut *old_node;
-            self.detach(node_ptr);
-            Some(old_node)
-        } else {
-            None
+    /// Returns an estimate of the number of bytes the cache has allocated: the buckets of its
+    /// hash table, a node per entry, the sigil nodes that delimit its list, the nodes set aside
+    /// by `try_reserve`, the epoch index and admission filter if any, and the eviction listener
+    /// and clock. The `LruCache` value itself is left out, and keys and values only count with
+    /// their inline size; see [`deep_memory_usage`] for what they own on the heap.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(100);
+    /// let empty = cache.memory_usage();
+    ///
+    /// cache.put(1, [0u8; 256]);
+    /// assert!(cache.memory_usage() >= empty + 256);
+    /// ```
+    ///
+    /// [`deep_memory_usage`]: #method.deep_memory_usage
+    pub fn memory_usage(&self) -> usize {
+        let sigils = 2 + usize::from(!self.mid.is_null()) + usize::from(!self.win.is_null());
+        let mut bytes =
+            heap_size::table_size::<(KeyRef<K>, with_alloc!(Node<K, V, E>))>(self.map.capacity())
+                + (self.len() + sigils + self.spare_len) * mem::size_of::<LruEntry<K, V, E>>()
+                + mem::size_of_val(&*self.clock);
+        if let Some(listener) = self.listener.as_ref() {
+            bytes += mem::size_of_val(&**listener);
         }
-    }
-
-    fn detach(&mut self, node: *mut LruEntry<K, V>) {
-        unsafe {
-            (*(*node).prev).next = (*node).next;
-            (*(*node).next).prev = (*node).prev;
+        bytes += self.epoch_usage.len() * mem::size_of::<(E, EpochUsage)>();
+        if let Some(index) = self.epoch_index.as_ref() {
+            bytes += index.len() * mem::size_of::<(E, EpochBucket<K, V, E>)>();
         }
+        if let Some(sketch) = self.sketch.as_ref() {
+            bytes += sketch.memory_usage();
+        }
+        bytes
     }
 
-    fn attach(&mut self, node: *mut LruEntry<K, V>) {
-        unsafe {
-            (*node).epoch = self.cur_epoch;
-            (*node).next = (*self.head).next;
-            (*node).prev = self.head;
-            (*self.head).next = node;
-            (*(*node).next).prev = node;
-        }
+    /// Returns [`memory_usage`] plus the memory the keys and values own on the heap, as
+    /// reported by their [`HeapSize`] implementations. Takes time linear in the number of
+    /// entries.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    /// cache.put(1, vec![0u8; 1024]);
+    ///
+    /// assert!(cache.deep_memory_usage() >= cache.memory_usage() + 1024);
+    /// ```
+    ///
+    /// [`memory_usage`]: #method.memory_usage
+    /// [`HeapSize`]: trait.HeapSize.html
+    pub fn deep_memory_usage(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let owned: usize = self
+            .map
+            .values()
+            .map(|node| unsafe {
+                (*node.key.as_ptr()).heap_size() + (*node.val.as_ptr()).heap_size()
+            })
+            .sum();
+        self.memory_usage() + owned
     }
-}
 
-impl<K, V, S, A: Clone + Allocator> Drop for LruCache<K, V, S, A> {
-    fn drop(&mut self) {
-        self.map.values_mut().for_each(|e| unsafe {
-            ptr::drop_in_place(e.key.as_mut_ptr());
-            ptr::drop_in_place(e.val.as_mut_ptr());
-        });
-        // We rebox the head/tail, and because these are maybe-uninit
-        // they do not have the absent k/v dropped.
-        unsafe {
-            let _head = *Box::from_raw_in(self.head, self.alloc.clone());
-            let _tail = *Box::from_raw_in(self.tail, self.alloc.clone());
+    /// Resizes the cache. If the new capacity is smaller than the total charge of the current
+    /// cache, least recently used entries are discarded until the rest fits or only pinned
+    /// entries are left.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache: LruCache<isize, &str> = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.resize(4);
+    /// cache.put(3, "c");
+    /// cache.put(4, "d");
+    ///
+    /// assert_eq!(cache.len(), 4);
+    /// assert_eq!(cache.get(&1), Some(&"a"));
+    /// assert_eq!(cache.get(&2), Some(&"b"));
+    /// assert_eq!(cache.get(&3), Some(&"c"));
+    /// assert_eq!(cache.get(&4), Some(&"d"));
+    /// ```
+    pub fn resize(&mut self, cap: usize) {
+        // return early if capacity doesn't change
+        if cap == self.cap {
+            return;
         }
-    }
-}
 
-impl<'a, K: Hash + Eq, V, S: BuildHasher> IntoIterator for &'a LruCache<K, V, S> {
-    type Item = (&'a K, &'a V);
-    type IntoIter = Iter<'a, K, V>;
+        while self.usage > cap && self.evict_lru(RemovalCause::Resize).is_some() {}
+        self.map.shrink_to_fit();
 
-    fn into_iter(self) -> Iter<'a, K, V> {
-        self.iter()
+        self.cap = cap;
+        self.update_segment_caps();
+        if !self.mid.is_null() {
+            self.demote_overflow();
+        }
+        if !self.win.is_null() {
+            self.sketch = Some(FrequencySketch::with_capacity(cap));
+            self.enforce_capacity(ptr::null_mut());
+        }
     }
+
+    /// Switches the cache to segmented LRU (SLRU) eviction, which keeps a single scan from
+    /// flushing out frequently used entries. Entries enter a probationary segment and are
+    /// promoted to a protected segment when they are accessed again. The protected segment
+    /// holds at most `protected_ratio` of the capacity; its least recently used entries are
+    /// demoted back to probation when it overflows. Eviction takes the least recently used
+    /// probationary entry first.
+    ///
+    /// A ratio of `0.0`, the default, turns segmentation off again, along with the admission
+    /// filter if there is one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `protected_ratio` is not between `0.0` and `1.0`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(4);
+    /// cache.set_protected_ratio(0.5);
+    ///
+    /// cache.put("hot", 1);
+    /// cache.get(&"hot");
+    ///
+    /// // a scan only churns the probationary segment
+    /// for key in ["a", "b", "c", "d", "e", "f"] {
+    ///     cache.put(key, 0);
+    /// }
+    ///
+    /// assert_eq!(cache.get(&"hot"), Some(&1));
+    /// ```
+    pub fn set_protected_ratio(&mut self, protected_ratio: f64) {
+        assert!(
+            (0.0..=1.0).contains(&protected_ratio),
+            "protected ratio must be between 0 and 1"
+        );
+        if protected_ratio == 0.0 {
+            self.set_admission_window(None);
+        }
+        self.protected_ratio = protected_ratio;
+        self.update_segment_caps();
+
+        if protected_ratio == 0.0 {
+            if !self.mid.is_null() {
+                // demote everything, after which mid is the first node of the list
+                self.demote_overflow();
+                self.detach(self.mid);
+                unsafe {
+                    let _mid = self.node_from_raw(self.mid);
+                }
+                self.mid = ptr::null_mut();
+            }
+        } else {
+            if self.mid.is_null() {
+                // every entry starts out on probation
+                self.mid = Node::into_raw(self.new_node(LruEntry::new_sigil(self.cur_epoch)));
+                self.link_after(self.mid, self.head);
+            }
+            self.demote_overflow();
+        }
+    }
+
+    /// Returns the share of the capacity reserved for the protected segment, or `0.0` if the
+    /// cache is not segmented.
+    pub fn protected_ratio(&self) -> f64 {
+        self.protected_ratio
+    }
+
+    /// Puts a W-TinyLFU admission filter in front of the cache, which keeps entries that are
+    /// used only once from pushing out popular ones. New entries enter a window segment that
+    /// holds `window_ratio` of the capacity, or at least the newest entry. The rest of the
+    /// capacity is segmented as described for [`set_protected_ratio`], with a protected ratio
+    /// of `0.8` unless one was set before.
+    ///
+    /// An entry that overflows the window joins the probationary segment, but if the cache is
+    /// full it is admitted only when a count-min sketch of recent accesses estimates that it is
+    /// used more often than the entry it would evict. Otherwise the entry itself is evicted
+    /// with the cause `RemovalCause::Rejected`, and returned by `push` if that insert rejected
+    /// it. Every `put`, `push`, `get`, `get_mut`, `get_or_insert` and `entry` call counts as
+    /// an access of its key, whether the key is cached or not.
+    ///
+    /// `None`, the default, removes the filter again and leaves the cache segmented.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_ratio` is not between `0.0` and `1.0`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(3);
+    /// cache.set_admission_window(Some(0.34));
+    ///
+    /// // "a" and "b" are popular even before they are cached
+    /// for _ in 0..3 {
+    ///     cache.get(&"a");
+    ///     cache.get(&"b");
+    /// }
+    /// cache.put("a", 1);
+    /// cache.put("b", 2);
+    /// cache.put("c", 3);
+    ///
+    /// // "c" leaves the window but is not worth evicting "a" for
+    /// assert_eq!(cache.push("d", 4), Some(("c", 3)));
+    /// assert!(cache.contains(&"a"));
+    /// assert!(cache.contains(&"b"));
+    /// assert!(cache.contains(&"d"));
+    /// ```
+    ///
+    /// [`set_protected_ratio`]: #method.set_protected_ratio
+    pub fn set_admission_window(&mut self, window_ratio: Option<f64>) {
+        match window_ratio {
+            Some(window_ratio) => {
+                assert!(
+                    (0.0..=1.0).contains(&window_ratio),
+                    "window ratio must be between 0 and 1"
+                );
+                if self.mid.is_null() {
+                    self.set_protected_ratio(0.8);
+                }
+                if self.win.is_null() {
+                    // every entry starts out in the main segments
+                    self.win = Node::into_raw(self.new_node(LruEntry::new_sigil(self.cur_epoch)));
+                    self.link_after(self.win, self.head);
+                    self.sketch = Some(FrequencySketch::with_capacity(self.cap));
+                }
+                self.window_ratio = window_ratio;
+                self.update_segment_caps();
+                self.demote_overflow();
+                self.enforce_capacity(ptr::null_mut());
+            }
+            None => {
+                if self.win.is_null() {
+                    return;
+                }
+                // the window's entries become the most recently used ones on probation
+                loop {
+                    let node = unsafe { (*self.win).prev };
+                    if node == self.head {
+                        break;
+                    }
+                    self.detach(node);
+                    self.link_after(node, self.mid);
+                }
+                self.detach(self.win);
+                unsafe {
+                    let _win = self.node_from_raw(self.win);
+                }
+                self.win = ptr::null_mut();
+                self.window_ratio = 0.0;
+                self.sketch = None;
+                self.update_segment_caps();
+                self.demote_overflow();
+            }
+        }
+    }
+
+    /// Returns the share of the capacity reserved for the admission window, or `None` if the
+    /// cache has no admission filter.
+    pub fn admission_window(&self) -> Option<f64> {
+        if self.win.is_null() {
+            None
+        } else {
+            Some(self.window_ratio)
+        }
+    }
+
+    /// Update the current epoch. The given epoch should be greater than the current epoch.
+    pub fn update_epoch(&mut self, epoch: E) {
+        assert!(epoch > self.cur_epoch);
+        self.cur_epoch = epoch;
+    }
+
+    pub fn current_epoch(&self) -> E {
+        self.cur_epoch
+    }
+
+    /// Keeps a secondary index that groups the entries by epoch, so that `evict_by_epoch` takes
+    /// time proportional to the number of entries it evicts and catches every entry that is
+    /// older than the given epoch, wherever it sits in the list. Maintaining the index makes
+    /// inserts and accesses logarithmic in the number of distinct epochs.
+    ///
+    /// Enabling the index builds it from the entries in the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(4);
+    /// cache.set_protected_ratio(0.5);
+    /// cache.set_epoch_index(true);
+    ///
+    /// cache.put(1, "a");
+    /// cache.get(&1);
+    /// cache.update_epoch(1);
+    /// cache.put(2, "b");
+    ///
+    /// // 1 is protected, so the probationary 2 is the least recently used entry
+    /// assert_eq!(cache.peek_lru(), Some((&2, &"b")));
+    /// cache.evict_by_epoch(1);
+    /// assert!(!cache.contains(&1));
+    /// assert!(cache.contains(&2));
+    /// ```
+    pub fn set_epoch_index(&mut self, enabled: bool) {
+        if !enabled {
+            self.epoch_index = None;
+            return;
+        }
+        if self.epoch_index.is_some() {
+            return;
+        }
+
+        self.epoch_index = Some(BTreeMap::new());
+        let mut node = unsafe { (*self.head).next };
+        while node != self.tail {
+            let next = unsafe { (*node).next };
+            if node != self.mid && node != self.win {
+                self.link_into_bucket(node);
+            }
+            node = next;
+        }
+    }
+
+    /// Returns whether the cache keeps an epoch index.
+    pub fn has_epoch_index(&self) -> bool {
+        self.epoch_index.is_some()
+    }
+
+    /// Returns the number of entries and their total charge for every epoch held by the cache,
+    /// from the oldest epoch to the newest one.
+    ///
+    /// The usage is kept up to date as entries come and go, so this takes time linear in the
+    /// number of distinct epochs rather than in the number of entries.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{EpochUsage, LruCache};
+    /// let mut cache = LruCache::weighted(10);
+    ///
+    /// cache.put_with_charge(1, "a", 2);
+    /// cache.put_with_charge(2, "b", 3);
+    /// cache.update_epoch(1);
+    /// cache.put_with_charge(3, "c", 4);
+    ///
+    /// assert_eq!(
+    ///     cache.epoch_histogram(),
+    ///     vec![
+    ///         (0, EpochUsage { len: 2, charge: 5 }),
+    ///         (1, EpochUsage { len: 1, charge: 4 }),
+    ///     ]
+    /// );
+    /// ```
+    pub fn epoch_histogram(&self) -> Vec<(E, EpochUsage)> {
+        self.epoch_usage
+            .iter()
+            .map(|(&epoch, &usage)| (epoch, usage))
+            .collect()
+    }
+
+    /// Sets which accesses refresh the epoch of an entry. Entries that are already in the cache
+    /// keep their epoch.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{EpochPolicy, LruCache};
+    /// let mut cache = LruCache::new(2);
+    /// cache.set_epoch_policy(EpochPolicy::RefreshOnWrite);
+    ///
+    /// cache.put(1, "a");
+    /// cache.update_epoch(1);
+    /// cache.get(&1);
+    /// assert_eq!(cache.pop_lru_by_epoch(1), Some((1, "a", 0)));
+    ///
+    /// cache.put(2, "b");
+    /// cache.update_epoch(2);
+    /// cache.put(2, "beta");
+    /// assert_eq!(cache.pop_lru_by_epoch(2), None);
+    /// ```
+    pub fn set_epoch_policy(&mut self, policy: EpochPolicy) {
+        self.epoch_policy = policy;
+    }
+
+    /// Returns which accesses refresh the epoch of an entry.
+    pub fn epoch_policy(&self) -> EpochPolicy {
+        self.epoch_policy
+    }
+
+    /// Removes and returns the least recently used entry along with its epoch if its epoch is
+    /// older than the given one, or `None` otherwise. Pinned entries are passed over, and
+    /// expired entries met on the way are reclaimed.
+    pub fn pop_lru_by_epoch(&mut self, epoch: E) -> Option<(K, V, E)> {
+        loop {
+            let node = self.lru_node()?;
+            if self.is_expired(node) {
+                self.evict_node(node, RemovalCause::Expired);
+                continue;
+            }
+            let node_epoch = unsafe { (*node).epoch };
+            if node_epoch >= epoch {
+                return None;
+            }
+            let (key, val) = self.evict_node(node, RemovalCause::Epoch);
+            return Some((key, val, node_epoch));
+        }
+    }
+
+    /// Evicts the entries whose epoch is older than the given one.
+    ///
+    /// Without an epoch index this walks the list from the least recently used entry and stops
+    /// at the first one that is not older, so in a segmented cache an old entry can survive
+    /// behind a newer one. With an index, see [`set_epoch_index`], every older entry is evicted.
+    /// Pinned entries are kept either way.
+    ///
+    /// [`set_epoch_index`]: #method.set_epoch_index
+    pub fn evict_by_epoch(&mut self, epoch: E) {
+        if let Some(mut index) = self.epoch_index.take() {
+            // the evicted entries are unlinked with the index out of the way, as their buckets
+            // are dropped as a whole
+            let newer = index.split_off(&epoch);
+            let mut pinned = Vec::new();
+            for bucket in index.into_values() {
+                let mut node = bucket.first;
+                while !node.is_null() {
+                    let next = unsafe { (*node).epoch_next };
+                    if unsafe { (*node).pins } > 0 {
+                        pinned.push(node);
+                    } else if self.is_expired(node) {
+                        self.evict_node(node, RemovalCause::Expired);
+                    } else {
+                        self.evict_node(node, RemovalCause::Epoch);
+                    }
+                    node = next;
+                }
+            }
+            self.epoch_index = Some(newer);
+            for node in pinned {
+                self.link_into_bucket(node);
+            }
+            self.map.shrink_to_fit();
+            return;
+        }
+
+        self.drain_by_epoch(epoch).for_each(drop);
+    }
+
+    /// Returns an iterator that removes and yields the entries whose epoch is older than the
+    /// given one along with their epochs, starting from the least recently used entry and
+    /// stopping at the first one that is not older, like repeated calls to
+    /// `pop_lru_by_epoch`. Entries are removed only as the iterator is advanced, so dropping
+    /// it early leaves the rest in the cache. The map is shrunk once the iterator is dropped.
+    ///
+    /// Unlike `evict_by_epoch`, this follows the list even if the cache has an epoch index.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(3);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.update_epoch(1);
+    /// cache.put(3, "c");
+    ///
+    /// let mut drain = cache.drain_by_epoch(1);
+    /// assert_eq!(drain.next(), Some((1, "a", 0)));
+    /// drop(drain);
+    ///
+    /// assert_eq!(cache.len(), 2);
+    /// assert_eq!(cache.drain_by_epoch(1).collect::<Vec<_>>(), vec![(2, "b", 0)]);
+    /// ```
+    pub fn drain_by_epoch(&mut self, epoch: E) -> with_alloc!(DrainByEpoch<'_, K, V, S, E>) {
+        DrainByEpoch { cache: self, epoch }
+    }
+
+    /// Retains only the entries for which the predicate returns `true`, visiting each entry
+    /// once in most-recently-used order. The other entries are removed, even if they are
+    /// pinned, and reported to the eviction listener as explicit removals. The entries that
+    /// are kept do not move in the LRU list.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(4);
+    ///
+    /// cache.put(("users", 1), "alice");
+    /// cache.put(("orders", 7), "pending");
+    /// cache.put(("users", 2), "bob");
+    ///
+    /// cache.retain(|&(table, _), _| table != "users");
+    /// assert_eq!(cache.len(), 1);
+    /// assert!(cache.contains(&("orders", 7)));
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.retain_with_epoch(|k, v, _| f(k, v));
+    }
+
+    /// Like [`retain`], but also passes the epoch of each entry to the predicate.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(4);
+    ///
+    /// cache.put(1, "a");
+    /// cache.update_epoch(1);
+    /// cache.put(2, "b");
+    /// cache.put(3, "c");
+    ///
+    /// cache.retain_with_epoch(|&k, _, epoch| epoch > 0 && k != 3);
+    /// assert_eq!(cache.iter().collect::<Vec<_>>(), vec![(&2, &"b")]);
+    /// ```
+    ///
+    /// [`retain`]: #method.retain
+    pub fn retain_with_epoch<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V, E) -> bool,
+    {
+        let mut cursor = unsafe { (*self.head).next };
+        while self
+            .extract_next(&mut cursor, |k, v, epoch| !f(k, v, epoch))
+            .is_some()
+        {}
+    }
+
+    /// Returns an iterator that removes and yields the entries for which the predicate returns
+    /// `true`, walking the list once in most-recently-used order. Entries are removed only as
+    /// the iterator is advanced, so dropping it early leaves the rest in the cache. Removed
+    /// entries are reported to the eviction listener as explicit removals, like with
+    /// [`retain`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(4);
+    ///
+    /// cache.put(1, 10);
+    /// cache.put(2, 20);
+    /// cache.put(3, 30);
+    ///
+    /// let odd: Vec<_> = cache.extract_if(|&k, _| k % 2 == 1).collect();
+    /// assert_eq!(odd, vec![(3, 30), (1, 10)]);
+    /// assert_eq!(cache.len(), 1);
+    /// ```
+    ///
+    /// [`retain`]: #method.retain
+    pub fn extract_if<F>(&mut self, pred: F) -> with_alloc!(ExtractIf<'_, K, V, S, E, F>)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let cursor = unsafe { (*self.head).next };
+        ExtractIf {
+            cache: self,
+            cursor,
+            pred,
+        }
+    }
+
+    /// Returns a cursor that starts at the most recently used entry, or at the ghost position if
+    /// the cache is empty. See [`CursorMut`] for how it moves.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    ///
+    /// let mut cursor = cache.cursor_mru();
+    /// assert_eq!(cursor.key(), Some(&2));
+    /// cursor.move_next();
+    /// assert_eq!(cursor.current(), Some((&1, &mut "a")));
+    /// cursor.move_next();
+    /// assert!(cursor.is_ghost());
+    /// ```
+    ///
+    /// [`CursorMut`]: struct.CursorMut.html
+    pub fn cursor_mru(&mut self) -> with_alloc!(CursorMut<'_, K, V, S, E>) {
+        let node = self.next_entry(self.head);
+        CursorMut::new(self, node)
+    }
+
+    /// Returns a cursor that starts at the least recently used entry, or at the ghost position
+    /// if the cache is empty. Unlike `peek_lru`, this does not pass over pinned entries.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(3);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.put(3, "c");
+    ///
+    /// let mut cursor = cache.cursor_lru();
+    /// cursor.move_prev();
+    /// cursor.move_current_to_mru();
+    /// assert_eq!(cursor.epoch(), Some(0));
+    ///
+    /// assert_eq!(cache.peek_lru(), Some((&1, &"a")));
+    /// assert_eq!(cache.iter().next(), Some((&2, &"b")));
+    /// ```
+    pub fn cursor_lru(&mut self) -> with_alloc!(CursorMut<'_, K, V, S, E>) {
+        let node = self.prev_entry(self.tail);
+        CursorMut::new(self, node)
+    }
+
+    /// Sets the time-to-live given to entries that are inserted without one of their own.
+    /// `None`, the default, means such entries never expire. Entries that are already in the
+    /// cache keep their expiration time.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{LruCache, MockClock};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let clock = Arc::new(MockClock::new());
+    /// let mut cache = LruCache::new(2);
+    /// cache.set_clock(clock.clone());
+    /// cache.set_ttl(Some(Duration::from_secs(60)));
+    ///
+    /// cache.put(1, "a");
+    /// clock.advance(Duration::from_secs(30));
+    /// assert!(cache.contains(&1));
+    /// clock.advance(Duration::from_secs(30));
+    /// assert!(!cache.contains(&1));
+    /// ```
+    pub fn set_ttl(&mut self, ttl: Option<Duration>) {
+        self.ttl = ttl;
+    }
+
+    /// Returns the cache-wide time-to-live.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Replaces the clock used to expire entries, which defaults to [`SystemClock`].
+    ///
+    /// [`SystemClock`]: struct.SystemClock.html
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Removes all entries that have outlived their time-to-live and returns how many were
+    /// removed. Expired entries are invisible to lookups either way, but they keep using
+    /// capacity until they are reclaimed by an access or by this method.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{LruCache, MockClock};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let clock = Arc::new(MockClock::new());
+    /// let mut cache = LruCache::new(3);
+    /// cache.set_clock(clock.clone());
+    ///
+    /// cache.put_with_ttl(1, "a", Duration::from_secs(1));
+    /// cache.put_with_ttl(2, "b", Duration::from_secs(2));
+    /// cache.put(3, "c");
+    ///
+    /// clock.advance(Duration::from_secs(1));
+    /// assert_eq!(cache.len(), 3);
+    /// assert_eq!(cache.evict_expired(), 1);
+    /// assert_eq!(cache.len(), 2);
+    /// ```
+    pub fn evict_expired(&mut self) -> usize {
+        let mut evicted = 0;
+        let mut node = unsafe { (*self.tail).prev };
+        while node != self.head {
+            let prev = unsafe { (*node).prev };
+            if self.is_expired(node) {
+                self.evict_node(node, RemovalCause::Expired);
+                evicted += 1;
+            }
+            node = prev;
+        }
+        evicted
+    }
+
+    /// Clears the contents of the cache, pinned entries included.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache: LruCache<isize, &str> = LruCache::new(2);
+    /// assert_eq!(cache.len(), 0);
+    ///
+    /// cache.put(1, "a");
+    /// assert_eq!(cache.len(), 1);
+    ///
+    /// cache.put(2, "b");
+    /// assert_eq!(cache.len(), 2);
+    ///
+    /// cache.clear();
+    /// assert_eq!(cache.len(), 0);
+    /// ```
+    pub fn clear(&mut self) {
+        while self.evict_lru(RemovalCause::Clear).is_some() {}
+
+        // what is left is pinned, which keeps entries from being evicted but not from being
+        // cleared
+        while self.pinned > 0 {
+            let mut node = unsafe { (*self.tail).prev };
+            while node == self.mid || node == self.win {
+                node = unsafe { (*node).prev };
+            }
+            self.evict_node(node, RemovalCause::Clear);
+        }
+    }
+
+    /// Returns a snapshot of the cache's hit, miss, insert and eviction counters.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::LruCache;
+    /// let mut cache = LruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.get(&1);
+    /// cache.get(&2);
+    ///
+    /// let stats = cache.stats();
+    /// assert_eq!(stats.inserts, 1);
+    /// assert_eq!(stats.hits, 1);
+    /// assert_eq!(stats.misses, 1);
+    /// ```
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Resets all counters of the cache to zero.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    /// Sets the listener that is called for every entry that leaves the cache, together with
+    /// the reason it was removed. Replaces any previously set listener.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{LruCache, RemovalCause};
+    /// use std::sync::mpsc;
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// let mut cache = LruCache::new(2);
+    /// cache.set_eviction_listener(move |k: &i32, v: &&'static str, cause| {
+    ///     tx.send((*k, *v, cause)).unwrap();
+    /// });
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.put(3, "c");
+    /// cache.pop(&2);
+    ///
+    /// assert_eq!(rx.try_recv(), Ok((1, "a", RemovalCause::Capacity)));
+    /// assert_eq!(rx.try_recv(), Ok((2, "b", RemovalCause::Explicit)));
+    /// ```
+    pub fn set_eviction_listener<L>(&mut self, listener: L)
+    where
+        L: EvictionListener<K, V> + Send + 'static,
+    {
+        self.listener = Some(Box::new(listener));
+    }
+
+    /// An iterator visiting all entries in most-recently used order. The iterator element type is
+    /// `(&K, &V)`. Entries that have expired but have not been reclaimed yet are visited too; call
+    /// [`evict_expired`] first to leave them out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lru::LruCache;
+    ///
+    /// let mut cache = LruCache::new(3);
+    /// cache.put("a", 1);
+    /// cache.put("b", 2);
+    /// cache.put("c", 3);
+    ///
+    /// for (key, val) in cache.iter() {
+    ///     println!("key: {} val: {}", key, val);
+    /// }
+    /// ```
+    ///
+    /// [`evict_expired`]: #method.evict_expired
+    pub fn iter(&self) -> Iter<'_, K, V, E> {
+        Iter {
+            len: self.len(),
+            ptr: unsafe { (*self.head).next },
+            end: unsafe { (*self.tail).prev },
+            sigils: [self.mid, self.win],
+            phantom: PhantomData,
+        }
+    }
+
+    /// An iterator visiting all entries in most-recently-used order, giving a mutable reference on
+    /// V.  The iterator element type is `(&K, &mut V)`. Like [`iter`], it also visits entries that
+    /// have expired but have not been reclaimed yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lru::LruCache;
+    ///
+    /// struct HddBlock {
+    ///     dirty: bool,
+    ///     data: [u8; 512]
+    /// }
+    ///
+    /// let mut cache = LruCache::new(3);
+    /// cache.put(0, HddBlock { dirty: false, data: [0x00; 512]});
+    /// cache.put(1, HddBlock { dirty: true,  data: [0x55; 512]});
+    /// cache.put(2, HddBlock { dirty: true,  data: [0x77; 512]});
+    ///
+    /// // write dirty blocks to disk.
+    /// for (block_id, block) in cache.iter_mut() {
+    ///     if block.dirty {
+    ///         // write block to disk
+    ///         block.dirty = false
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`iter`]: #method.iter
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V, E> {
+        IterMut {
+            len: self.len(),
+            ptr: unsafe { (*self.head).next },
+            end: unsafe { (*self.tail).prev },
+            sigils: [self.mid, self.win],
+            phantom: PhantomData,
+        }
+    }
+
+    fn remove_last(&mut self) -> Option<with_alloc!(Node<K, V, E>)> {
+        let node = self.lru_node()?;
+        let old_key = KeyRef {
+            k: unsafe { (*node).key.as_ptr() },
+        };
+        let mut old_node = self.map.remove(&old_key).unwrap();
+        let node_ptr: *mut LruEntry<K, V, E> = &mut *old_node;
+        self.unlink(node_ptr);
+        Some(old_node)
+    }
+
+    // Returns the node that will be evicted next. In segmented mode that is the least recently
+    // used node of the probationary segment, or of the protected segment if the former is empty,
+    // or of the window if both are. Pinned nodes are passed over.
+    fn lru_node(&self) -> Option<*mut LruEntry<K, V, E>> {
+        self.lru_node_before(self.tail)
+    }
+
+    // Returns the node that would be evicted next if the nodes from `node` to the tail were
+    // not in the list.
+    fn lru_node_before(&self, node: *mut LruEntry<K, V, E>) -> Option<*mut LruEntry<K, V, E>> {
+        let mut node = unsafe { (*node).prev };
+        while node == self.mid || node == self.win || unsafe { (*node).pins } > 0 {
+            node = unsafe { (*node).prev };
+        }
+        if node == self.head {
+            None
+        } else {
+            Some(node)
+        }
+    }
+
+    fn notify(&mut self, key: &K, val: &V, cause: RemovalCause) {
+        #[cfg(feature = "stats")]
+        match cause {
+            RemovalCause::Capacity => self.stats.capacity_evictions += 1,
+            RemovalCause::Epoch => self.stats.epoch_evictions += 1,
+            RemovalCause::Explicit => self.stats.explicit_pops += 1,
+            RemovalCause::Replaced => self.stats.updates += 1,
+            RemovalCause::Expired => self.stats.expirations += 1,
+            RemovalCause::Rejected => self.stats.rejections += 1,
+            RemovalCause::Resize | RemovalCause::Clear => {}
+        }
+        if let Some(listener) = self.listener.as_mut() {
+            listener.on_evict(key, val, cause);
+        }
+    }
+
+    #[cfg_attr(not(feature = "stats"), allow(unused_variables))]
+    fn record_lookup(&mut self, hit: bool) {
+        #[cfg(feature = "stats")]
+        if hit {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+    }
+
+    fn record_access(&mut self, hash: u64) {
+        if let Some(sketch) = self.sketch.as_mut() {
+            sketch.increment(hash);
+        }
+    }
+
+    fn record_insert(&mut self) {
+        #[cfg(feature = "stats")]
+        {
+            self.stats.inserts += 1;
+        }
+    }
+
+    fn detach(&mut self, node: *mut LruEntry<K, V, E>) {
+        unsafe {
+            (*(*node).prev).next = (*node).next;
+            (*(*node).next).prev = (*node).prev;
+            match (*node).segment {
+                Segment::Window => self.window_usage -= (*node).charge,
+                Segment::Protected => self.protected_usage -= (*node).charge,
+                Segment::Probation => {}
+            }
+            (*node).segment = Segment::Probation;
+        }
+    }
+
+    // Links a new node in as the most recently used one. In segmented mode new nodes start out
+    // at the head of the probationary segment, or of the window with an admission filter.
+    fn attach(&mut self, node: *mut LruEntry<K, V, E>) {
+        unsafe {
+            (*node).epoch = self.cur_epoch;
+        }
+        self.index(node);
+        if !self.win.is_null() {
+            self.link_into_window(node);
+            return;
+        }
+        let anchor = if self.mid.is_null() {
+            self.head
+        } else {
+            self.mid
+        };
+        self.link_after(node, anchor);
+    }
+
+    // Moves a node that was accessed to the head of the list. In segmented mode this promotes
+    // the node into the protected segment, unless it is still in the admission window.
+    fn touch(&mut self, node: *mut LruEntry<K, V, E>, refresh_epoch: bool) {
+        let windowed = unsafe { (*node).segment == Segment::Window };
+        self.detach(node);
+        if refresh_epoch {
+            self.set_epoch(node, self.cur_epoch);
+        }
+        if windowed {
+            self.link_into_window(node);
+            return;
+        }
+        let anchor = if self.win.is_null() {
+            self.head
+        } else {
+            self.win
+        };
+        self.link_after(node, anchor);
+
+        if !self.mid.is_null() {
+            unsafe {
+                (*node).segment = Segment::Protected;
+                self.protected_usage += (*node).charge;
+            }
+            self.demote_overflow();
+        }
+    }
+
+    fn set_epoch(&mut self, node: *mut LruEntry<K, V, E>, epoch: E) {
+        if unsafe { (*node).epoch } != epoch {
+            self.unindex(node);
+            unsafe {
+                (*node).epoch = epoch;
+            }
+            self.index(node);
+        }
+    }
+
+    // Moves the least recently used protected nodes back to the head of the probationary
+    // segment until the protected segment fits its share of the capacity.
+    fn demote_overflow(&mut self) {
+        while self.protected_usage > self.protected_cap {
+            let node = unsafe { (*self.mid).prev };
+            self.detach(node);
+            self.link_after(node, self.mid);
+        }
+    }
+
+    // Takes a node that is leaving the cache out of the list and the epoch index.
+    fn unlink(&mut self, node: *mut LruEntry<K, V, E>) {
+        self.detach(node);
+        self.unindex(node);
+        unsafe {
+            self.usage -= (*node).charge;
+            if (*node).pins > 0 {
+                self.pinned -= 1;
+            }
+        }
+    }
+
+    // Counts a node in the usage of its epoch and adds it to the epoch index, if any.
+    fn index(&mut self, node: *mut LruEntry<K, V, E>) {
+        unsafe {
+            let usage = self.epoch_usage.entry((*node).epoch).or_default();
+            usage.len += 1;
+            usage.charge += (*node).charge;
+        }
+        self.link_into_bucket(node);
+    }
+
+    // Adds a node to the front of the entries with its epoch in the epoch index, if any.
+    fn link_into_bucket(&mut self, node: *mut LruEntry<K, V, E>) {
+        if let Some(index) = self.epoch_index.as_mut() {
+            let bucket = index
+                .entry(unsafe { (*node).epoch })
+                .or_insert(EpochBucket {
+                    first: ptr::null_mut(),
+                });
+            unsafe {
+                (*node).epoch_prev = ptr::null_mut();
+                (*node).epoch_next = bucket.first;
+                if !bucket.first.is_null() {
+                    (*bucket.first).epoch_prev = node;
+                }
+            }
+            bucket.first = node;
+        }
+    }
+
+    fn unindex(&mut self, node: *mut LruEntry<K, V, E>) {
+        unsafe {
+            let epoch = (*node).epoch;
+            let usage = self.epoch_usage.get_mut(&epoch).unwrap();
+            usage.len -= 1;
+            usage.charge -= (*node).charge;
+            if usage.len == 0 {
+                self.epoch_usage.remove(&epoch);
+            }
+        }
+        if let Some(index) = self.epoch_index.as_mut() {
+            unsafe {
+                let (prev, next) = ((*node).epoch_prev, (*node).epoch_next);
+                if !next.is_null() {
+                    (*next).epoch_prev = prev;
+                }
+                if !prev.is_null() {
+                    (*prev).epoch_next = next;
+                } else if next.is_null() {
+                    index.remove(&(*node).epoch);
+                } else {
+                    index.get_mut(&(*node).epoch).unwrap().first = next;
+                }
+            }
+        }
+    }
+
+    fn link_into_window(&mut self, node: *mut LruEntry<K, V, E>) {
+        self.link_after(node, self.head);
+        unsafe {
+            (*node).segment = Segment::Window;
+            self.window_usage += (*node).charge;
+        }
+    }
+
+    fn update_segment_caps(&mut self) {
+        self.window_cap = if self.win.is_null() {
+            0
+        } else {
+            (self.cap as f64 * self.window_ratio) as usize
+        };
+        self.protected_cap = ((self.cap - self.window_cap) as f64 * self.protected_ratio) as usize;
+    }
+
+    // Evicts entries until the cache fits its capacity, sparing `keep`, and returns the first
+    // entry that was evicted. With an admission filter, the entries that overflow the window
+    // move to probation first, and each one that does not fit is weighed against the entry
+    // that would make room for it.
+    fn enforce_capacity(&mut self, keep: *mut LruEntry<K, V, E>) -> Option<(K, V)> {
+        let mut evicted = None;
+        if !self.win.is_null() {
+            while self.window_usage > self.window_cap {
+                let candidate = unsafe { (*self.win).prev };
+                if candidate == keep {
+                    break;
+                }
+                self.detach(candidate);
+                self.link_after(candidate, self.mid);
+
+                while self.usage > self.cap {
+                    let victim = match self.lru_node() {
+                        Some(victim) => victim,
+                        None => break,
+                    };
+                    // a pinned candidate cannot be turned away
+                    let admitted = victim != candidate
+                        && (unsafe { (*candidate).pins } > 0 || self.admits(candidate, victim));
+                    let (node, cause) = if admitted {
+                        (victim, RemovalCause::Capacity)
+                    } else if victim == candidate {
+                        (candidate, RemovalCause::Capacity)
+                    } else {
+                        (candidate, RemovalCause::Rejected)
+                    };
+                    let removed = self.evict_node(node, cause);
+                    evicted = evicted.or(Some(removed));
+                    if node == candidate {
+                        break;
+                    }
+                }
+            }
+        }
+
+        while self.usage > self.cap {
+            // `keep` is spared, which leaves the victim in front of it when it was inserted cold
+            let victim = match self.lru_node() {
+                Some(node) if node == keep => self.lru_node_before(keep),
+                victim => victim,
+            };
+            match victim {
+                Some(node) => {
+                    let removed = self.evict_node(node, RemovalCause::Capacity);
+                    evicted = evicted.or(Some(removed));
+                }
+                // the rest is pinned, so the cache stays over its capacity
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    // Whether the admission filter lets `candidate` take the place of `victim`, which requires
+    // the candidate to have been accessed more often recently.
+    fn admits(&self, candidate: *mut LruEntry<K, V, E>, victim: *mut LruEntry<K, V, E>) -> bool {
+        let sketch = self.sketch.as_ref().unwrap();
+        let frequency = |node: *mut LruEntry<K, V, E>| {
+            sketch.frequency(self.hash_key(unsafe { &*(*node).key.as_ptr() }))
+        };
+        frequency(candidate) > frequency(victim)
+    }
+
+    // Returns the entry after `node` in the list, passing over the sigils, or null at the tail.
+    fn next_entry(&self, node: *mut LruEntry<K, V, E>) -> *mut LruEntry<K, V, E> {
+        let mut next = unsafe { (*node).next };
+        while next == self.mid || next == self.win {
+            next = unsafe { (*next).next };
+        }
+        if next == self.tail {
+            ptr::null_mut()
+        } else {
+            next
+        }
+    }
+
+    // Returns the entry before `node` in the list, passing over the sigils, or null at the head.
+    fn prev_entry(&self, node: *mut LruEntry<K, V, E>) -> *mut LruEntry<K, V, E> {
+        let mut prev = unsafe { (*node).prev };
+        while prev == self.mid || prev == self.win {
+            prev = unsafe { (*prev).prev };
+        }
+        if prev == self.head {
+            ptr::null_mut()
+        } else {
+            prev
+        }
+    }
+
+    // Moves a node to the tail of the list, where it is the next one to be evicted. In
+    // segmented mode it joins the probationary segment.
+    fn move_to_tail(&mut self, node: *mut LruEntry<K, V, E>) {
+        self.detach(node);
+        let anchor = unsafe { (*self.tail).prev };
+        self.link_after(node, anchor);
+    }
+
+    // Advances `cursor` towards the tail until the predicate picks a node, which is removed as
+    // an explicit removal and handed back. The cursor is left on the node after the picked one,
+    // so removing it does not disturb the walk.
+    fn extract_next<F>(
+        &mut self,
+        cursor: &mut *mut LruEntry<K, V, E>,
+        mut pred: F,
+    ) -> Option<(K, V)>
+    where
+        F: FnMut(&K, &mut V, E) -> bool,
+    {
+        while *cursor != self.tail {
+            let node = *cursor;
+            *cursor = unsafe { (*node).next };
+            if node == self.mid || node == self.win {
+                continue;
+            }
+            let picked = unsafe {
+                pred(
+                    &*(*node).key.as_ptr(),
+                    &mut *(*node).val.as_mut_ptr(),
+                    (*node).epoch,
+                )
+            };
+            if picked {
+                return Some(self.evict_node(node, RemovalCause::Explicit));
+            }
+        }
+        None
+    }
+
+    fn evict_node(&mut self, node: *mut LruEntry<K, V, E>, cause: RemovalCause) -> (K, V) {
+        let hash = self.hash_key(unsafe { &*(*node).key.as_ptr() });
+        self.remove_node(hash, node, cause)
+    }
+
+    fn link_after(&mut self, node: *mut LruEntry<K, V, E>, anchor: *mut LruEntry<K, V, E>) {
+        unsafe {
+            (*node).next = (*anchor).next;
+            (*node).prev = anchor;
+            (*anchor).next = node;
+            (*(*node).next).prev = node;
+        }
+    }
+}
+
+impl<K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> Drop for with_alloc!(LruCache<K, V, S, E>) {
+    fn drop(&mut self) {
+        if let Some(listener) = self.listener.as_mut() {
+            let mut node = unsafe { (*self.tail).prev };
+            while node != self.head {
+                unsafe {
+                    if node != self.mid && node != self.win {
+                        listener.on_evict(
+                            &*(*node).key.as_ptr(),
+                            &*(*node).val.as_ptr(),
+                            RemovalCause::Clear,
+                        );
+                    }
+                    node = (*node).prev;
+                }
+            }
+        }
+        self.map.values_mut().for_each(|e| unsafe {
+            ptr::drop_in_place(e.key.as_mut_ptr());
+            ptr::drop_in_place(e.val.as_mut_ptr());
+        });
+        // We rebox the head/tail, and because these are maybe-uninit
+        // they do not have the absent k/v dropped.
+        unsafe {
+            let _head = self.node_from_raw(self.head);
+            let _tail = self.node_from_raw(self.tail);
+            if !self.mid.is_null() {
+                let _mid = self.node_from_raw(self.mid);
+            }
+            if !self.win.is_null() {
+                let _win = self.node_from_raw(self.win);
+            }
+            while !self.spare.is_null() {
+                let next = (*self.spare).next;
+                let _spare = self.node_from_raw(self.spare);
+                self.spare = next;
+            }
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher, E: Epoch> IntoIterator for &'a LruCache<K, V, S, E> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, E>;
+
+    fn into_iter(self) -> Iter<'a, K, V, E> {
+        self.iter()
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher, E: Epoch> IntoIterator for &'a mut LruCache<K, V, S, E> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V, E>;
+
+    fn into_iter(self) -> IterMut<'a, K, V, E> {
+        self.iter_mut()
+    }
+}
+
+// The compiler does not automatically derive Send and Sync for LruCache because it contains
+// raw pointers. The raw pointers are safely encapsulated by LruCache though so we can
+// implement Send and Sync for it below.
+unsafe impl<
+        K: Send,
+        V: Send,
+        S: Send,
+        E: Send,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator + Send,
+    > Send for with_alloc!(LruCache<K, V, S, E>)
+{
+}
+unsafe impl<
+        K: Sync,
+        V: Sync,
+        S: Sync,
+        E: Sync,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator + Sync,
+    > Sync for with_alloc!(LruCache<K, V, S, E>)
+{
+}
+
+impl<K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> Clone for with_alloc!(LruCache<K, V, S, E>)
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher + Clone,
+    E: Epoch,
+{
+    /// Returns a copy of the cache with the same entries in the same recency order, along with
+    /// their epochs, charges, expiration times, pins and segments, and the same hasher and
+    /// settings. The eviction listener is not cloned and the clock is shared.
+    fn clone(&self) -> Self {
+        #[cfg(feature = "allocator-api")]
+        let map = HashMap::with_capacity_and_hasher_in(
+            self.map.len(),
+            self.map.hasher().clone(),
+            self.map.allocator().clone(),
+        );
+        #[cfg(not(feature = "allocator-api"))]
+        let map = HashMap::with_capacity_and_hasher(self.map.len(), self.map.hasher().clone());
+        let mut cache = LruCache::construct(self.cap, map, self.cur_epoch);
+        cache.protected_ratio = self.protected_ratio;
+        cache.protected_cap = self.protected_cap;
+        cache.window_ratio = self.window_ratio;
+        cache.window_cap = self.window_cap;
+        cache.sketch = self.sketch.clone();
+        cache.epoch_policy = self.epoch_policy;
+        cache.ttl = self.ttl;
+        cache.clock = self.clock.clone();
+        #[cfg(feature = "stats")]
+        {
+            cache.stats = self.stats;
+        }
+        if !self.mid.is_null() {
+            cache.mid = Node::into_raw(self.new_node(LruEntry::new_sigil(self.cur_epoch)));
+        }
+        if !self.win.is_null() {
+            cache.win = Node::into_raw(self.new_node(LruEntry::new_sigil(self.cur_epoch)));
+        }
+        if self.epoch_index.is_some() {
+            cache.epoch_index = Some(BTreeMap::new());
+        }
+
+        // copy the list node by node rather than inserting the entries, which could evict some
+        // of them from a cache that is over its capacity
+        let mut last = cache.head;
+        let mut node = unsafe { (*self.head).next };
+        while node != self.tail {
+            let copy = if node == self.mid {
+                cache.mid
+            } else if node == self.win {
+                cache.win
+            } else {
+                let mut entry = unsafe {
+                    LruEntry::new(
+                        (*(*node).key.as_ptr()).clone(),
+                        (*(*node).val.as_ptr()).clone(),
+                        (*node).epoch,
+                        (*node).charge,
+                        (*node).expires_at,
+                    )
+                };
+                entry.pins = unsafe { (*node).pins };
+                entry.segment = unsafe { (*node).segment };
+                let mut copy = self.new_node(entry);
+                let copy_ptr: *mut LruEntry<K, V, E> = &mut *copy;
+                let hash = cache.hash_key(unsafe { &*(*copy_ptr).key.as_ptr() });
+                let keyref = KeyRef {
+                    k: unsafe { (*copy_ptr).key.as_ptr() },
+                };
+                match cache.map.raw_entry_mut().from_hash(hash, |_| false) {
+                    RawEntryMut::Vacant(entry) => {
+                        entry.insert_hashed_nocheck(hash, keyref, copy);
+                    }
+                    RawEntryMut::Occupied(_) => unreachable!(),
+                }
+                cache.index(copy_ptr);
+                copy_ptr
+            };
+            cache.link_after(copy, last);
+            last = copy;
+            node = unsafe { (*node).next };
+        }
+        cache.usage = self.usage;
+        cache.pinned = self.pinned;
+        cache.protected_usage = self.protected_usage;
+        cache.window_usage = self.window_usage;
+        cache
+    }
+}
+
+impl<K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> PartialEq for with_alloc!(LruCache<K, V, S, E>)
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    S: BuildHasher,
+    E: Epoch,
+{
+    /// Two caches are equal if they hold equal entries in the same recency order. Capacities,
+    /// epochs and settings are not compared.
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> Eq for with_alloc!(LruCache<K, V, S, E>)
+where
+    K: Hash + Eq,
+    V: Eq,
+    S: BuildHasher,
+    E: Epoch,
+{
+}
+
+impl<K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> Extend<(K, V)> for with_alloc!(LruCache<K, V, S, E>)
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    E: Epoch,
+{
+    /// Puts the pairs into the cache one by one in iteration order, so that the last one ends
+    /// up as the most recently used entry.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.put(k, v);
+        }
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for LruCache<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    /// Creates an unbounded cache holding the pairs, the last one as the most recently used
+    /// entry. Use `resize` to bound it afterwards.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut cache = LruCache::unbounded_with_hasher(S::default());
+        cache.extend(iter);
+        cache
+    }
+}
+
+impl<K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> fmt::Debug for with_alloc!(LruCache<K, V, S, E>)
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    E: Epoch,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LruCache")
+            .field("len", &self.len())
+            .field("cap", &self.cap())
+            .finish()
+    }
+}
+
+/// An iterator over the entries of a `LruCache`.
+///
+/// This `struct` is created by the [`iter`] method on [`LruCache`][`LruCache`]. See its
+/// documentation for more.
+///
+/// [`iter`]: struct.LruCache.html#method.iter
+/// [`LruCache`]: struct.LruCache.html
+pub struct Iter<'a, K: 'a, V: 'a, E = u64> {
+    len: usize,
+
+    ptr: *const LruEntry<K, V, E>,
+    end: *const LruEntry<K, V, E>,
+    // the segment boundaries of a segmented cache, which hold no entries
+    sigils: [*const LruEntry<K, V, E>; 2],
+
+    phantom: PhantomData<&'a K>,
+}
+
+impl<'a, K, V, E> Iterator for Iter<'a, K, V, E> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.len == 0 {
+            return None;
+        }
+        while self.sigils.contains(&self.ptr) {
+            self.ptr = unsafe { (*self.ptr).next };
+        }
+
+        let key = unsafe { &(*(*self.ptr).key.as_ptr()) as &K };
+        let val = unsafe { &(*(*self.ptr).val.as_ptr()) as &V };
+
+        self.len -= 1;
+        self.ptr = unsafe { (*self.ptr).next };
+
+        Some((key, val))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+
+    fn count(self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, K, V, E> DoubleEndedIterator for Iter<'a, K, V, E> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.len == 0 {
+            return None;
+        }
+        while self.sigils.contains(&self.end) {
+            self.end = unsafe { (*self.end).prev };
+        }
+
+        let key = unsafe { &(*(*self.end).key.as_ptr()) as &K };
+        let val = unsafe { &(*(*self.end).val.as_ptr()) as &V };
+
+        self.len -= 1;
+        self.end = unsafe { (*self.end).prev };
+
+        Some((key, val))
+    }
+}
+
+impl<'a, K, V, E> ExactSizeIterator for Iter<'a, K, V, E> {}
+impl<'a, K, V, E> FusedIterator for Iter<'a, K, V, E> {}
+
+impl<'a, K, V, E> Clone for Iter<'a, K, V, E> {
+    fn clone(&self) -> Iter<'a, K, V, E> {
+        Iter {
+            len: self.len,
+            ptr: self.ptr,
+            end: self.end,
+            sigils: self.sigils,
+            phantom: PhantomData,
+        }
+    }
+}
+
+// Lists the entries that are left, so that `cache.iter()` shows the contents of any cache whose
+// keys and values implement `Debug`.
+impl<'a, K: fmt::Debug, V: fmt::Debug, E> fmt::Debug for Iter<'a, K, V, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+// The compiler does not automatically derive Send and Sync for Iter because it contains
+// raw pointers.
+unsafe impl<'a, K: Send, V: Send, E: Send> Send for Iter<'a, K, V, E> {}
+unsafe impl<'a, K: Sync, V: Sync, E: Sync> Sync for Iter<'a, K, V, E> {}
+
+/// An iterator over mutables entries of a `LruCache`.
+///
+/// This `struct` is created by the [`iter_mut`] method on [`LruCache`][`LruCache`]. See its
+/// documentation for more.
+///
+/// [`iter_mut`]: struct.LruCache.html#method.iter_mut
+/// [`LruCache`]: struct.LruCache.html
+pub struct IterMut<'a, K: 'a, V: 'a, E = u64> {
+    len: usize,
+
+    ptr: *mut LruEntry<K, V, E>,
+    end: *mut LruEntry<K, V, E>,
+    // the segment boundary of a segmented cache, which holds no entry
+    sigils: [*mut LruEntry<K, V, E>; 2],
+
+    phantom: PhantomData<&'a K>,
+}
+
+impl<'a, K, V, E> Iterator for IterMut<'a, K, V, E> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        if self.len == 0 {
+            return None;
+        }
+        while self.sigils.contains(&self.ptr) {
+            self.ptr = unsafe { (*self.ptr).next };
+        }
+
+        let key = unsafe { &mut (*(*self.ptr).key.as_mut_ptr()) as &mut K };
+        let val = unsafe { &mut (*(*self.ptr).val.as_mut_ptr()) as &mut V };
+
+        self.len -= 1;
+        self.ptr = unsafe { (*self.ptr).next };
+
+        Some((key, val))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+
+    fn count(self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, K, V, E> DoubleEndedIterator for IterMut<'a, K, V, E> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        if self.len == 0 {
+            return None;
+        }
+        while self.sigils.contains(&self.end) {
+            self.end = unsafe { (*self.end).prev };
+        }
+
+        let key = unsafe { &mut (*(*self.end).key.as_mut_ptr()) as &mut K };
+        let val = unsafe { &mut (*(*self.end).val.as_mut_ptr()) as &mut V };
+
+        self.len -= 1;
+        self.end = unsafe { (*self.end).prev };
+
+        Some((key, val))
+    }
+}
+
+impl<'a, K, V, E> ExactSizeIterator for IterMut<'a, K, V, E> {}
+impl<'a, K, V, E> FusedIterator for IterMut<'a, K, V, E> {}
+
+// The compiler does not automatically derive Send and Sync for Iter because it contains
+// raw pointers.
+unsafe impl<'a, K: Send, V: Send, E: Send> Send for IterMut<'a, K, V, E> {}
+unsafe impl<'a, K: Sync, V: Sync, E: Sync> Sync for IterMut<'a, K, V, E> {}
+
+/// An iterator that moves out of a `LruCache`.
+///
+/// It yields every entry that is still in the cache, from the least to the most recently used
+/// one, including pinned entries and expired entries that were not reclaimed yet.
+///
+/// This `struct` is created by the [`into_iter`] method on [`LruCache`][`LruCache`]. See its
+/// documentation for more.
+///
+/// [`into_iter`]: struct.LruCache.html#method.into_iter
+/// [`LruCache`]: struct.LruCache.html
+pub struct IntoIter<K, V, E = u64>
+where
+    K: Hash + Eq,
+    E: Epoch,
+{
+    cache: LruCache<K, V, DefaultHasher, E>,
+}
+
+impl<K, V, E> Iterator for IntoIter<K, V, E>
+where
+    K: Hash + Eq,
+    E: Epoch,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        // unlike `pop_lru`, pinned entries are handed out as well
+        let node = self.cache.prev_entry(self.cache.tail);
+        if node.is_null() {
+            return None;
+        }
+        Some(self.cache.evict_node(node, RemovalCause::Explicit))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.cache.len();
+        (len, Some(len))
+    }
+
+    fn count(self) -> usize {
+        self.cache.len()
+    }
+}
+
+impl<K, V, E> ExactSizeIterator for IntoIter<K, V, E>
+where
+    K: Hash + Eq,
+    E: Epoch,
+{
+}
+impl<K, V, E> FusedIterator for IntoIter<K, V, E>
+where
+    K: Hash + Eq,
+    E: Epoch,
+{
+}
+
+impl<K: Hash + Eq, V, E: Epoch> IntoIterator for LruCache<K, V, DefaultHasher, E> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, E>;
+
+    fn into_iter(self) -> IntoIter<K, V, E> {
+        IntoIter { cache: self }
+    }
+}
+
+/// A draining iterator over the entries of a `LruCache` that are older than an epoch.
+///
+/// This `struct` is created by the [`drain_by_epoch`] method on [`LruCache`][`LruCache`]. See
+/// its documentation for more.
+///
+/// [`drain_by_epoch`]: struct.LruCache.html#method.drain_by_epoch
+/// [`LruCache`]: struct.LruCache.html
+pub struct DrainByEpoch<'a, K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    E: Epoch,
+{
+    cache: &'a mut with_alloc!(LruCache<K, V, S, E>),
+    epoch: E,
+}
+
+impl<'a, K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> Iterator
+    for with_alloc!(DrainByEpoch<'a, K, V, S, E>)
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    E: Epoch,
+{
+    type Item = (K, V, E);
+
+    fn next(&mut self) -> Option<(K, V, E)> {
+        self.cache.pop_lru_by_epoch(self.epoch)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.cache.len()))
+    }
+}
+
+impl<'a, K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> FusedIterator
+    for with_alloc!(DrainByEpoch<'a, K, V, S, E>)
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    E: Epoch,
+{
+}
+
+impl<'a, K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> Drop
+    for with_alloc!(DrainByEpoch<'a, K, V, S, E>)
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    E: Epoch,
+{
+    fn drop(&mut self) {
+        self.cache.map.shrink_to_fit();
+    }
+}
+
+/// An iterator that removes and yields the entries of a `LruCache` that match a predicate.
+///
+/// This `struct` is created by the [`extract_if`] method on [`LruCache`][`LruCache`]. See its
+/// documentation for more.
+///
+/// [`extract_if`]: struct.LruCache.html#method.extract_if
+/// [`LruCache`]: struct.LruCache.html
+pub struct ExtractIf<'a, K, V, S, E, F, #[cfg(feature = "allocator-api")] A: Clone + Allocator>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    E: Epoch,
+    F: FnMut(&K, &mut V) -> bool,
+{
+    cache: &'a mut with_alloc!(LruCache<K, V, S, E>),
+    cursor: *mut LruEntry<K, V, E>,
+    pred: F,
+}
+
+impl<'a, K, V, S, E, F, #[cfg(feature = "allocator-api")] A: Clone + Allocator> Iterator
+    for with_alloc!(ExtractIf<'a, K, V, S, E, F>)
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    E: Epoch,
+    F: FnMut(&K, &mut V) -> bool,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let pred = &mut self.pred;
+        self.cache
+            .extract_next(&mut self.cursor, |k, v, _| pred(k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.cache.len()))
+    }
+}
+
+impl<'a, K, V, S, E, F, #[cfg(feature = "allocator-api")] A: Clone + Allocator> FusedIterator
+    for with_alloc!(ExtractIf<'a, K, V, S, E, F>)
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    E: Epoch,
+    F: FnMut(&K, &mut V) -> bool,
+{
 }
 
-impl<'a, K: Hash + Eq, V, S: BuildHasher> IntoIterator for &'a mut LruCache<K, V, S> {
-    type Item = (&'a K, &'a mut V);
-    type IntoIter = IterMut<'a, K, V>;
+#[cfg(test)]
+mod tests {
+    use super::LruCache;
+    use scoped_threadpool::Pool;
+    use std::fmt::Debug;
+    use std::mem;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn assert_opt_eq<V: PartialEq + Debug>(opt: Option<&V>, v: V) {
+        assert!(opt.is_some());
+        assert_eq!(opt.unwrap(), &v);
+    }
+
+    fn assert_opt_eq_mut<V: PartialEq + Debug>(opt: Option<&mut V>, v: V) {
+        assert!(opt.is_some());
+        assert_eq!(opt.unwrap(), &v);
+    }
+
+    fn assert_opt_eq_tuple<K: PartialEq + Debug, V: PartialEq + Debug>(
+        opt: Option<(&K, &V)>,
+        kv: (K, V),
+    ) {
+        assert!(opt.is_some());
+        let res = opt.unwrap();
+        assert_eq!(res.0, &kv.0);
+        assert_eq!(res.1, &kv.1);
+    }
+
+    fn assert_opt_eq_mut_tuple<K: PartialEq + Debug, V: PartialEq + Debug>(
+        opt: Option<(&K, &mut V)>,
+        kv: (K, V),
+    ) {
+        assert!(opt.is_some());
+        let res = opt.unwrap();
+        assert_eq!(res.0, &kv.0);
+        assert_eq!(res.1, &kv.1);
+    }
+
+    #[test]
+    fn test_unbounded() {
+        let mut cache = LruCache::unbounded();
+        for i in 0..13370 {
+            cache.put(i, ());
+        }
+        assert_eq!(cache.len(), 13370);
+    }
+
+    #[test]
+    fn test_with_hasher() {
+        use hashbrown::hash_map::DefaultHashBuilder;
+
+        let s = DefaultHashBuilder::default();
+        let mut cache = LruCache::with_hasher(16, s);
+
+        for i in 0..13370 {
+            cache.put(i, ());
+        }
+        assert_eq!(cache.len(), 16);
+    }
+
+    #[test]
+    fn test_put_and_get() {
+        let mut cache = LruCache::new(2);
+        assert!(cache.is_empty());
+
+        assert_eq!(cache.put("apple", "red"), None);
+        assert_eq!(cache.put("banana", "yellow"), None);
+
+        assert_eq!(cache.cap(), 2);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.is_empty());
+        assert_opt_eq(cache.get(&"apple"), "red");
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn test_put_and_get_or_insert() {
+        let mut cache = LruCache::new(2);
+        assert!(cache.is_empty());
+
+        assert_eq!(cache.put("apple", "red"), None);
+        assert_eq!(cache.put("banana", "yellow"), None);
+
+        assert_eq!(cache.cap(), 2);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.is_empty());
+        assert_opt_eq(cache.get_or_insert(&"apple", || "orange"), &"red");
+        assert_opt_eq(cache.get_or_insert(&"banana", || "orange"), &"yellow");
+        assert_opt_eq(cache.get_or_insert(&"lemon", || "orange"), &"orange");
+        assert_opt_eq(cache.get_or_insert(&"lemon", || "red"), &"orange");
+    }
+
+    #[test]
+    fn test_put_and_get_mut() {
+        let mut cache = LruCache::new(2);
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_eq!(cache.cap(), 2);
+        assert_eq!(cache.len(), 2);
+        assert_opt_eq_mut(cache.get_mut(&"apple"), "red");
+        assert_opt_eq_mut(cache.get_mut(&"banana"), "yellow");
+    }
+
+    #[test]
+    fn test_get_mut_and_update() {
+        let mut cache = LruCache::new(2);
+
+        cache.put("apple", 1);
+        cache.put("banana", 3);
+
+        {
+            let v = cache.get_mut(&"apple").unwrap();
+            *v = 4;
+        }
+
+        assert_eq!(cache.cap(), 2);
+        assert_eq!(cache.len(), 2);
+        assert_opt_eq_mut(cache.get_mut(&"apple"), 4);
+        assert_opt_eq_mut(cache.get_mut(&"banana"), 3);
+    }
+
+    #[test]
+    fn test_put_update() {
+        let mut cache = LruCache::new(1);
+
+        assert_eq!(cache.put("apple", "red"), None);
+        assert_eq!(cache.put("apple", "green"), Some("red"));
+
+        assert_eq!(cache.len(), 1);
+        assert_opt_eq(cache.get(&"apple"), "green");
+    }
+
+    #[test]
+    fn test_put_removes_oldest() {
+        let mut cache = LruCache::new(2);
+
+        assert_eq!(cache.put("apple", "red"), None);
+        assert_eq!(cache.put("banana", "yellow"), None);
+        assert_eq!(cache.put("pear", "green"), None);
+
+        assert!(cache.get(&"apple").is_none());
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+        assert_opt_eq(cache.get(&"pear"), "green");
+
+        // Even though we inserted "apple" into the cache earlier it has since been removed from
+        // the cache so there is no current value for `put` to return.
+        assert_eq!(cache.put("apple", "green"), None);
+        assert_eq!(cache.put("tomato", "red"), None);
+
+        assert!(cache.get(&"pear").is_none());
+        assert_opt_eq(cache.get(&"apple"), "green");
+        assert_opt_eq(cache.get(&"tomato"), "red");
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut cache = LruCache::new(2);
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_opt_eq(cache.peek(&"banana"), "yellow");
+        assert_opt_eq(cache.peek(&"apple"), "red");
+
+        cache.put("pear", "green");
+
+        assert!(cache.peek(&"apple").is_none());
+        assert_opt_eq(cache.peek(&"banana"), "yellow");
+        assert_opt_eq(cache.peek(&"pear"), "green");
+    }
+
+    #[test]
+    fn test_peek_mut() {
+        let mut cache = LruCache::new(2);
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_opt_eq_mut(cache.peek_mut(&"banana"), "yellow");
+        assert_opt_eq_mut(cache.peek_mut(&"apple"), "red");
+        assert!(cache.peek_mut(&"pear").is_none());
+
+        cache.put("pear", "green");
+
+        assert!(cache.peek_mut(&"apple").is_none());
+        assert_opt_eq_mut(cache.peek_mut(&"banana"), "yellow");
+        assert_opt_eq_mut(cache.peek_mut(&"pear"), "green");
+
+        {
+            let v = cache.peek_mut(&"banana").unwrap();
+            *v = "green";
+        }
+
+        assert_opt_eq_mut(cache.peek_mut(&"banana"), "green");
+    }
+
+    #[test]
+    fn test_peek_lru() {
+        let mut cache = LruCache::new(2);
+
+        assert!(cache.peek_lru().is_none());
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+        assert_opt_eq_tuple(cache.peek_lru(), ("apple", "red"));
+
+        cache.get(&"apple");
+        assert_opt_eq_tuple(cache.peek_lru(), ("banana", "yellow"));
 
-    fn into_iter(self) -> IterMut<'a, K, V> {
-        self.iter_mut()
+        cache.clear();
+        assert!(cache.peek_lru().is_none());
     }
-}
 
-// The compiler does not automatically derive Send and Sync for LruCache because it contains
-// raw pointers. The raw pointers are safely encapsulated by LruCache though so we can
-// implement Send and Sync for it below.
-unsafe impl<K: Send, V: Send, S: Send, A: Clone + Allocator + Send> Send for LruCache<K, V, S, A> {}
-unsafe impl<K: Sync, V: Sync, S: Sync, A: Clone + Allocator + Sync> Sync for LruCache<K, V, S, A> {}
+    #[test]
+    fn test_contains() {
+        let mut cache = LruCache::new(2);
 
-impl<K: Hash + Eq, V> fmt::Debug for LruCache<K, V> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("LruCache")
-            .field("len", &self.len())
-            .field("cap", &self.cap())
-            .finish()
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+        cache.put("pear", "green");
+
+        assert!(!cache.contains(&"apple"));
+        assert!(cache.contains(&"banana"));
+        assert!(cache.contains(&"pear"));
     }
-}
 
-/// An iterator over the entries of a `LruCache`.
-///
-/// This `struct` is created by the [`iter`] method on [`LruCache`][`LruCache`]. See its
-/// documentation for more.
-///
-/// [`iter`]: struct.LruCache.html#method.iter
-/// [`LruCache`]: struct.LruCache.html
-pub struct Iter<'a, K: 'a, V: 'a> {
-    len: usize,
+    #[test]
+    fn test_pop() {
+        let mut cache = LruCache::new(2);
 
-    ptr: *const LruEntry<K, V>,
-    end: *const LruEntry<K, V>,
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
 
-    phantom: PhantomData<&'a K>,
-}
+        assert_eq!(cache.len(), 2);
+        assert_opt_eq(cache.get(&"apple"), "red");
+        assert_opt_eq(cache.get(&"banana"), "yellow");
 
-impl<'a, K, V> Iterator for Iter<'a, K, V> {
-    type Item = (&'a K, &'a V);
+        let popped = cache.pop(&"apple");
+        assert!(popped.is_some());
+        assert_eq!(popped.unwrap(), "red");
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&"apple").is_none());
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+    }
 
-    fn next(&mut self) -> Option<(&'a K, &'a V)> {
-        if self.len == 0 {
-            return None;
+    #[test]
+    fn test_pop_entry() {
+        let mut cache = LruCache::new(2);
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_eq!(cache.len(), 2);
+        assert_opt_eq(cache.get(&"apple"), "red");
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+
+        let popped = cache.pop_entry(&"apple");
+        assert!(popped.is_some());
+        assert_eq!(popped.unwrap(), ("apple", "red"));
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&"apple").is_none());
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+    }
+
+    #[test]
+    fn test_pop_lru() {
+        let mut cache = LruCache::new(200);
+
+        for i in 0..75 {
+            cache.put(i, "A");
+        }
+        for i in 0..75 {
+            cache.put(i + 100, "B");
         }
+        for i in 0..75 {
+            cache.put(i + 200, "C");
+        }
+        assert_eq!(cache.len(), 200);
 
-        let key = unsafe { &(*(*self.ptr).key.as_ptr()) as &K };
-        let val = unsafe { &(*(*self.ptr).val.as_ptr()) as &V };
+        for i in 0..75 {
+            assert_opt_eq(cache.get(&(74 - i + 100)), "B");
+        }
+        assert_opt_eq(cache.get(&25), "A");
 
-        self.len -= 1;
-        self.ptr = unsafe { (*self.ptr).next };
+        for i in 26..75 {
+            assert_eq!(cache.pop_lru(), Some((i, "A")));
+        }
+        for i in 0..75 {
+            assert_eq!(cache.pop_lru(), Some((i + 200, "C")));
+        }
+        for i in 0..75 {
+            assert_eq!(cache.pop_lru(), Some((74 - i + 100, "B")));
+        }
+        assert_eq!(cache.pop_lru(), Some((25, "A")));
+        for _ in 0..50 {
+            assert_eq!(cache.pop_lru(), None);
+        }
+    }
 
-        Some((key, val))
+    #[test]
+    fn test_clear() {
+        let mut cache = LruCache::new(2);
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_eq!(cache.len(), 2);
+        assert_opt_eq(cache.get(&"apple"), "red");
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+
+        cache.clear();
+        assert_eq!(cache.len(), 0);
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.len, Some(self.len))
+    #[test]
+    fn test_resize_larger() {
+        let mut cache = LruCache::new(2);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.resize(4);
+        cache.put(3, "c");
+        cache.put(4, "d");
+
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.get(&2), Some(&"b"));
+        assert_eq!(cache.get(&3), Some(&"c"));
+        assert_eq!(cache.get(&4), Some(&"d"));
     }
 
-    fn count(self) -> usize {
-        self.len
+    #[test]
+    fn test_resize_smaller() {
+        let mut cache = LruCache::new(4);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        cache.put(4, "d");
+
+        cache.resize(2);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&2).is_none());
+        assert_eq!(cache.get(&3), Some(&"c"));
+        assert_eq!(cache.get(&4), Some(&"d"));
     }
-}
 
-impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
-    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
-        if self.len == 0 {
-            return None;
+    #[test]
+    fn test_entry() {
+        use super::Entry;
+
+        let mut cache = LruCache::new(2);
+
+        assert_opt_eq_mut(cache.entry("apple").or_insert("red"), "red");
+        assert_opt_eq_mut(cache.entry("banana").or_insert("yellow"), "yellow");
+        assert_opt_eq_mut(cache.entry("apple").or_insert("green"), "red");
+
+        // "apple" was promoted by the last lookup, so "banana" is the victim
+        match cache.entry("pear") {
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), &"pear");
+                let (value, evicted) = entry.push("green");
+                assert_eq!(value, Some(&mut "green"));
+                assert_eq!(evicted, Some(("banana", "yellow")));
+            }
+            Entry::Occupied(_) => panic!("pear should not be cached"),
         }
 
-        let key = unsafe { &(*(*self.end).key.as_ptr()) as &K };
-        let val = unsafe { &(*(*self.end).val.as_ptr()) as &V };
+        match cache.entry("apple") {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.insert("crimson"), "red");
+                assert_eq!(entry.remove_entry(), ("apple", "crimson"));
+            }
+            Entry::Vacant(_) => panic!("apple should be cached"),
+        }
 
-        self.len -= 1;
-        self.end = unsafe { (*self.end).prev };
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.usage(), 1);
+        assert!(!cache.contains(&"apple"));
+    }
 
-        Some((key, val))
+    #[test]
+    fn test_peek_entry_does_not_promote() {
+        let mut cache = LruCache::new(2);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.peek_entry(1).and_modify(|v| *v = "c");
+        cache.put(3, "d");
+
+        assert!(!cache.contains(&1));
+        assert!(cache.contains(&2));
     }
-}
 
-impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
-impl<'a, K, V> FusedIterator for Iter<'a, K, V> {}
+    #[test]
+    fn test_entry_insert_resets_the_ttl() {
+        use super::{Entry, MockClock};
+        use std::sync::Arc;
+        use std::time::Duration;
 
-impl<'a, K, V> Clone for Iter<'a, K, V> {
-    fn clone(&self) -> Iter<'a, K, V> {
-        Iter {
-            len: self.len,
-            ptr: self.ptr,
-            end: self.end,
-            phantom: PhantomData,
+        let clock = Arc::new(MockClock::new());
+        let mut cache = LruCache::new(2);
+        cache.set_clock(clock.clone());
+        cache.set_ttl(Some(Duration::from_secs(10)));
+        cache.put_with_ttl(1, "a", Duration::from_secs(1));
+        cache.put_with_ttl(2, "b", Duration::from_secs(1));
+
+        // like `put` on an existing key, `insert` restarts the default time-to-live
+        cache.put(1, "alpha");
+        if let Entry::Occupied(mut entry) = cache.entry(2) {
+            assert_eq!(entry.insert("beta"), "b");
         }
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get(&1), Some(&"alpha"));
+        assert_eq!(cache.get(&2), Some(&"beta"));
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), None);
     }
-}
 
-// The compiler does not automatically derive Send and Sync for Iter because it contains
-// raw pointers.
-unsafe impl<'a, K: Send, V: Send> Send for Iter<'a, K, V> {}
-unsafe impl<'a, K: Sync, V: Sync> Sync for Iter<'a, K, V> {}
+    #[test]
+    fn test_entry_zero_cap() {
+        let mut cache = LruCache::new(0);
+        assert_eq!(cache.entry(1).or_insert_with(|| "a"), None);
+        assert!(cache.is_empty());
+    }
 
-/// An iterator over mutables entries of a `LruCache`.
-///
-/// This `struct` is created by the [`iter_mut`] method on [`LruCache`][`LruCache`]. See its
-/// documentation for more.
-///
-/// [`iter_mut`]: struct.LruCache.html#method.iter_mut
-/// [`LruCache`]: struct.LruCache.html
-pub struct IterMut<'a, K: 'a, V: 'a> {
-    len: usize,
+    #[test]
+    fn test_ttl() {
+        use super::MockClock;
+        use std::sync::Arc;
+        use std::time::Duration;
 
-    ptr: *mut LruEntry<K, V>,
-    end: *mut LruEntry<K, V>,
+        let clock = Arc::new(MockClock::new());
+        let mut cache = LruCache::new(4);
+        cache.set_clock(clock.clone());
+        cache.set_ttl(Some(Duration::from_secs(10)));
 
-    phantom: PhantomData<&'a K>,
-}
+        cache.put(1, "a");
+        cache.put_with_ttl(2, "b", Duration::from_secs(20));
+        clock.advance(Duration::from_secs(5));
+        cache.put(3, "c");
 
-impl<'a, K, V> Iterator for IterMut<'a, K, V> {
-    type Item = (&'a K, &'a mut V);
+        clock.advance(Duration::from_secs(5));
+        assert!(!cache.contains(&1));
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.peek_mut(&1), None);
+        assert_opt_eq(cache.peek(&3), "c");
+        // peeking does not reclaim the entry
+        assert_eq!(cache.len(), 3);
 
-    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
-        if self.len == 0 {
-            return None;
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.len(), 2);
+
+        // an expired entry is replaced as if it were missing
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.put(3, "d"), None);
+        assert_opt_eq(cache.get_or_insert(3, || "e"), "d");
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.evict_expired(), 1);
+        assert!(!cache.contains(&2));
+        assert_opt_eq(cache.get(&3), "d");
+    }
+
+    #[test]
+    fn test_pops_skip_expired_entries() {
+        use super::MockClock;
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let clock = Arc::new(MockClock::new());
+        let mut cache = LruCache::new(4);
+        cache.set_clock(clock.clone());
+
+        cache.put_with_ttl(1, "a", Duration::from_secs(1));
+        cache.put_with_ttl(2, "b", Duration::from_secs(1));
+        cache.put(3, "c");
+        cache.put_with_ttl(4, "d", Duration::from_secs(1));
+        clock.advance(Duration::from_secs(1));
+
+        assert_eq!(cache.peek_lru(), Some((&3, &"c")));
+        assert_eq!(cache.pop(&4), None);
+        assert_eq!(cache.pop_entry(&4), None);
+        assert_eq!(cache.len(), 3);
+
+        // 1 and 2 are reclaimed on the way to 3
+        assert_eq!(cache.pop_lru(), Some((3, "c")));
+        assert_eq!(cache.pop_lru(), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_drain_by_epoch_skips_expired_entries() {
+        use super::{MockClock, RemovalCause};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let clock = Arc::new(MockClock::new());
+        let mut cache = LruCache::new(4);
+        cache.set_clock(clock.clone());
+        let log = removed.clone();
+        cache.set_eviction_listener(move |k: &i32, _: &&str, cause| {
+            log.lock().unwrap().push((*k, cause))
+        });
+
+        cache.put_with_ttl(1, "a", Duration::from_secs(1));
+        cache.put(2, "b");
+        cache.put_with_ttl(3, "c", Duration::from_secs(1));
+        cache.update_epoch(1);
+        cache.put(4, "d");
+        clock.advance(Duration::from_secs(1));
+
+        assert_eq!(
+            cache.drain_by_epoch(1).collect::<Vec<_>>(),
+            vec![(2, "b", 0)]
+        );
+        assert_eq!(
+            *removed.lock().unwrap(),
+            vec![
+                (1, RemovalCause::Expired),
+                (2, RemovalCause::Epoch),
+                (3, RemovalCause::Expired)
+            ]
+        );
+        assert_eq!(cache.iter().collect::<Vec<_>>(), vec![(&4, &"d")]);
+    }
+
+    #[test]
+    fn test_into_iter_yields_expired_entries() {
+        use super::MockClock;
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let clock = Arc::new(MockClock::new());
+        let mut cache = LruCache::new(3);
+        cache.set_clock(clock.clone());
+        cache.put_with_ttl(1, "a", Duration::from_secs(1));
+        cache.put(2, "b");
+        clock.advance(Duration::from_secs(1));
+
+        let iter = cache.into_iter();
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.collect::<Vec<_>>(), vec![(1, "a"), (2, "b")]);
+    }
+
+    #[test]
+    fn test_slru_promotes_on_second_hit() {
+        let mut cache = LruCache::new(4);
+        cache.set_protected_ratio(0.5);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.get(&1);
+        cache.get(&2);
+
+        // 1 and 2 are protected, so the scan only cycles through probation
+        for i in 3..10 {
+            cache.put(i, "scan");
         }
+        assert!(cache.contains(&1));
+        assert!(cache.contains(&2));
+        assert_eq!(cache.len(), 4);
 
-        let key = unsafe { &mut (*(*self.ptr).key.as_mut_ptr()) as &mut K };
-        let val = unsafe { &mut (*(*self.ptr).val.as_mut_ptr()) as &mut V };
+        // promoting a third entry demotes 1 back to the head of probation
+        cache.get(&9);
+        assert_eq!(
+            cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![9, 2, 1, 8]
+        );
+        assert_eq!(
+            cache.iter().rev().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![8, 1, 2, 9]
+        );
+
+        cache.put(10, "scan");
+        cache.put(11, "scan");
+        assert!(!cache.contains(&1));
+        assert!(cache.contains(&2));
+        assert_eq!(cache.peek_lru(), Some((&10, &"scan")));
+    }
 
-        self.len -= 1;
-        self.ptr = unsafe { (*self.ptr).next };
+    #[test]
+    fn test_slru_evicts_protected_when_probation_is_empty() {
+        let mut cache = LruCache::new(2);
+        cache.set_protected_ratio(1.0);
 
-        Some((key, val))
-    }
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.get(&1);
+        cache.get(&2);
+        assert_eq!(cache.peek_lru(), Some((&1, &"a")));
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.len, Some(self.len))
-    }
+        cache.update_epoch(1);
+        cache.put(3, "c");
+        assert!(!cache.contains(&1));
+        cache.get(&3);
 
-    fn count(self) -> usize {
-        self.len
+        assert_eq!(cache.pop_lru_by_epoch(1), Some((2, "b", 0)));
+        assert_eq!(cache.pop_lru_by_epoch(1), None);
+        cache.evict_by_epoch(2);
+        assert!(cache.is_empty());
+        assert_eq!(cache.iter().count(), 0);
     }
-}
 
-impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
-    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
-        if self.len == 0 {
-            return None;
-        }
+    #[test]
+    fn test_slru_can_be_turned_off() {
+        let mut cache = LruCache::new(3);
+        cache.set_protected_ratio(0.7);
 
-        let key = unsafe { &mut (*(*self.end).key.as_mut_ptr()) as &mut K };
-        let val = unsafe { &mut (*(*self.end).val.as_mut_ptr()) as &mut V };
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        cache.get(&1);
 
-        self.len -= 1;
-        self.end = unsafe { (*self.end).prev };
+        cache.set_protected_ratio(0.0);
+        assert_eq!(
+            cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![1, 3, 2]
+        );
 
-        Some((key, val))
+        cache.put(4, "d");
+        assert!(!cache.contains(&2));
+        cache.get(&3);
+        cache.put(5, "e");
+        assert!(!cache.contains(&1));
     }
-}
 
-impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
-impl<'a, K, V> FusedIterator for IterMut<'a, K, V> {}
+    #[test]
+    fn test_admission_rejects_one_hit_wonders() {
+        use super::RemovalCause;
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+        use std::sync::{Arc, Mutex};
+
+        let rejected = Arc::new(Mutex::new(Vec::new()));
+        let mut cache = LruCache::with_hasher(10, BuildHasherDefault::<DefaultHasher>::default());
+        cache.set_admission_window(Some(0.1));
+        assert_eq!(cache.admission_window(), Some(0.1));
+        assert_eq!(cache.protected_ratio(), 0.8);
+        let log = rejected.clone();
+        cache.set_eviction_listener(move |k: &u32, _: &u32, cause| {
+            if cause == RemovalCause::Rejected {
+                log.lock().unwrap().push(*k);
+            }
+        });
 
-// The compiler does not automatically derive Send and Sync for Iter because it contains
-// raw pointers.
-unsafe impl<'a, K: Send, V: Send> Send for IterMut<'a, K, V> {}
-unsafe impl<'a, K: Sync, V: Sync> Sync for IterMut<'a, K, V> {}
+        for i in 0..10 {
+            cache.put(i, i);
+        }
+        for _ in 0..3 {
+            for i in 0..10 {
+                cache.get(&i);
+            }
+        }
+        for i in 100..150 {
+            cache.put(i, i);
+        }
 
-/// An iterator that moves out of a `LruCache`.
-///
-/// This `struct` is created by the [`into_iter`] method on [`LruCache`][`LruCache`]. See its
-/// documentation for more.
-///
-/// [`into_iter`]: struct.LruCache.html#method.into_iter
-/// [`LruCache`]: struct.LruCache.html
-pub struct IntoIter<K, V>
-where
-    K: Hash + Eq,
-{
-    cache: LruCache<K, V>,
-}
+        // 9 is still in the window when the scan starts and ties with the entry it would
+        // replace, which keeps its place; every key of the scan but the last one is turned away
+        assert_eq!(
+            cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![149, 8, 7, 6, 5, 4, 3, 2, 1, 0]
+        );
+        let expected: Vec<u32> = Some(9).into_iter().chain(100..149).collect();
+        assert_eq!(*rejected.lock().unwrap(), expected);
+    }
 
-impl<K, V> Iterator for IntoIter<K, V>
-where
-    K: Hash + Eq,
-{
-    type Item = (K, V);
+    #[test]
+    fn test_admission_window_can_be_removed() {
+        let mut cache = LruCache::new(4);
+        cache.set_admission_window(Some(0.5));
 
-    fn next(&mut self) -> Option<(K, V)> {
-        self.cache.pop_lru()
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        cache.put(4, "d");
+        cache.get(&1);
+        cache.get(&4);
+        assert_eq!(
+            cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![4, 3, 1, 2]
+        );
+        assert_eq!(
+            cache.iter_mut().rev().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![2, 1, 3, 4]
+        );
+        assert_eq!(cache.peek_lru(), Some((&2, &"b")));
+
+        cache.set_admission_window(None);
+        assert_eq!(cache.admission_window(), None);
+        assert_eq!(cache.protected_ratio(), 0.8);
+        assert_eq!(
+            cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![1, 4, 3, 2]
+        );
+
+        cache.set_admission_window(Some(0.25));
+        cache.set_protected_ratio(0.0);
+        assert_eq!(cache.admission_window(), None);
+        assert_eq!(cache.pop_lru(), Some((2, "b")));
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.iter().count(), 0);
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let len = self.cache.len();
-        (len, Some(len))
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_round_trip() {
+        let mut cache = LruCache::weighted(5);
+        cache.put(1, String::from("a"));
+        cache.put(2, String::from("b"));
+        cache.update_epoch(1);
+        cache.put(3, String::from("c"));
+        cache.get(&1);
+        cache.put_with_charge(4, String::from("d"), 2);
+
+        let json = serde_json::to_string(&cache).unwrap();
+        assert_eq!(
+            json,
+            r#"{"cap":5,"cur_epoch":1,"entries":[[2,"b",0,1],[3,"c",1,1],[1,"a",1,1],[4,"d",1,2]]}"#
+        );
+
+        let mut restored: LruCache<u32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.cap(), 5);
+        assert_eq!(restored.usage(), 5);
+        assert_eq!(restored.current_epoch(), 1);
+        assert!(restored.iter().eq(cache.iter()));
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+
+        assert_eq!(
+            restored.pop_lru_by_epoch(1),
+            Some((2, String::from("b"), 0))
+        );
+        assert_eq!(restored.pop_lru_by_epoch(1), None);
     }
 
-    fn count(self) -> usize {
-        self.cache.len()
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_round_trip_with_explicit_epochs() {
+        let mut cache = LruCache::new(3);
+        cache.update_epoch(10);
+        cache.put_with_epoch(1, 1, 3);
+        cache.put_with_epoch(2, 2, 10);
+        cache.put(3, 3);
+
+        let json = serde_json::to_string(&cache).unwrap();
+        let restored: LruCache<u32, u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.epoch_of(&1), Some(3));
+        assert_eq!(restored.epoch_of(&2), Some(10));
+        assert!(restored.iter().eq(cache.iter()));
     }
-}
 
-impl<K, V> ExactSizeIterator for IntoIter<K, V> where K: Hash + Eq {}
-impl<K, V> FusedIterator for IntoIter<K, V> where K: Hash + Eq {}
+    #[test]
+    #[should_panic(expected = "an entry's epoch cannot be ahead of the current epoch")]
+    fn test_put_with_epoch_rejects_future_epochs() {
+        let mut cache = LruCache::new(2);
+        cache.put_with_epoch(1, 1, 10);
+    }
 
-impl<K: Hash + Eq, V> IntoIterator for LruCache<K, V> {
-    type Item = (K, V);
-    type IntoIter = IntoIter<K, V>;
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_round_trip_of_pinned_and_expired_entries() {
+        use super::MockClock;
+        use std::sync::Arc;
+        use std::time::Duration;
 
-    fn into_iter(self) -> IntoIter<K, V> {
-        IntoIter { cache: self }
+        // 1 no longer fits once it is unpinned
+        let mut cache = LruCache::new(2);
+        cache.put(1, 1);
+        cache.put(2, 2);
+        cache.pin(&1);
+        cache.pin(&2);
+        cache.put(3, 3);
+        assert!(cache.is_overcommitted());
+        let json = serde_json::to_string(&cache).unwrap();
+        let restored: LruCache<u32, u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            restored.iter().collect::<Vec<_>>(),
+            vec![(&3, &3), (&2, &2)]
+        );
+
+        // 1 has expired and is left out
+        let clock = Arc::new(MockClock::new());
+        let mut cache = LruCache::new(2);
+        cache.set_clock(clock.clone());
+        cache.put_with_ttl(1, 1, Duration::from_secs(1));
+        cache.put(2, 2);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(
+            serde_json::to_string(&cache).unwrap(),
+            r#"{"cap":2,"cur_epoch":0,"entries":[[2,2,0,1]]}"#
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::LruCache;
-    use scoped_threadpool::Pool;
-    use std::fmt::Debug;
-    use std::sync::atomic::{AtomicUsize, Ordering};
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_rejects_inconsistent_caches() {
+        fn restore(json: &str) -> String {
+            serde_json::from_str::<LruCache<u32, u32>>(json)
+                .err()
+                .unwrap()
+                .to_string()
+        }
 
-    fn assert_opt_eq<V: PartialEq + Debug>(opt: Option<&V>, v: V) {
-        assert!(opt.is_some());
-        assert_eq!(opt.unwrap(), &v);
+        assert!(
+            restore(r#"{"cap":2,"cur_epoch":0,"entries":[[1,1,0,1],[1,2,0,1]]}"#)
+                .starts_with("duplicate key in entries")
+        );
+        assert!(restore(r#"{"cap":2,"cur_epoch":0,"entries":[[1,1,3,1]]}"#)
+            .starts_with("an entry epoch is ahead of the current epoch"));
+        assert!(restore(r#"{"cap":2,"entries":[]}"#).starts_with("missing field `cur_epoch`"));
     }
 
-    fn assert_opt_eq_mut<V: PartialEq + Debug>(opt: Option<&mut V>, v: V) {
-        assert!(opt.is_some());
-        assert_eq!(opt.unwrap(), &v);
-    }
+    #[test]
+    fn test_put_with_charge() {
+        let mut cache = LruCache::weighted(10);
 
-    fn assert_opt_eq_tuple<K: PartialEq + Debug, V: PartialEq + Debug>(
-        opt: Option<(&K, &V)>,
-        kv: (K, V),
-    ) {
-        assert!(opt.is_some());
-        let res = opt.unwrap();
-        assert_eq!(res.0, &kv.0);
-        assert_eq!(res.1, &kv.1);
-    }
+        assert_eq!(cache.put_with_charge(1, "a", 3), None);
+        assert_eq!(cache.put_with_charge(2, "b", 3), None);
+        assert_eq!(cache.put_with_charge(3, "c", 3), None);
+        assert_eq!(cache.usage(), 9);
 
-    fn assert_opt_eq_mut_tuple<K: PartialEq + Debug, V: PartialEq + Debug>(
-        opt: Option<(&K, &mut V)>,
-        kv: (K, V),
-    ) {
-        assert!(opt.is_some());
-        let res = opt.unwrap();
-        assert_eq!(res.0, &kv.0);
-        assert_eq!(res.1, &kv.1);
-    }
+        // 1 and 2 both have to go to make room for 4
+        assert_eq!(cache.push_with_charge(4, "d", 5), Some((1, "a")));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.usage(), 8);
+        assert!(!cache.contains(&2));
 
-    #[test]
-    fn test_unbounded() {
-        let mut cache = LruCache::unbounded();
-        for i in 0..13370 {
-            cache.put(i, ());
-        }
-        assert_eq!(cache.len(), 13370);
+        // growing an existing entry evicts others as well
+        assert_eq!(cache.put_with_charge(4, "e", 8), Some("d"));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.usage(), 8);
+        assert_opt_eq(cache.get(&4), "e");
     }
 
     #[test]
-    fn test_with_hasher() {
-        use hashbrown::hash_map::DefaultHashBuilder;
+    fn test_put_with_charge_larger_than_cap() {
+        let mut cache = LruCache::weighted(10);
 
-        let s = DefaultHashBuilder::default();
-        let mut cache = LruCache::with_hasher(16, s);
+        cache.put_with_charge(1, "a", 5);
+        assert_eq!(cache.put_with_charge(2, "b", 11), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.usage(), 5);
 
-        for i in 0..13370 {
-            cache.put(i, ());
-        }
-        assert_eq!(cache.len(), 16);
+        assert_eq!(cache.put_with_charge(1, "c", 11), Some("a"));
+        assert!(cache.is_empty());
+        assert_eq!(cache.usage(), 0);
     }
 
     #[test]
-    fn test_put_and_get() {
-        let mut cache = LruCache::new(2);
-        assert!(cache.is_empty());
+    fn test_put_keeps_the_charge_of_an_existing_entry() {
+        use super::DefaultHasher;
 
-        assert_eq!(cache.put("apple", "red"), None);
-        assert_eq!(cache.put("banana", "yellow"), None);
+        let mut cache = LruCache::weighted_with_hasher(10, DefaultHasher::default());
 
-        assert_eq!(cache.cap(), 2);
-        assert_eq!(cache.len(), 2);
-        assert!(!cache.is_empty());
-        assert_opt_eq(cache.get(&"apple"), "red");
-        assert_opt_eq(cache.get(&"banana"), "yellow");
+        cache.put_with_charge(1, "a", 6);
+        assert_eq!(cache.put(1, "alpha"), Some("a"));
+        assert_eq!(cache.usage(), 6);
+        assert_eq!(cache.push(1, "aleph"), Some((1, "alpha")));
+        assert_eq!(cache.usage(), 6);
+
+        cache.put(2, "b");
+        assert_eq!(cache.usage(), 7);
+        assert_eq!(cache.put_with_charge(2, "beta", 4), Some("b"));
+        assert_eq!(cache.usage(), 10);
     }
 
     #[test]
-    fn test_put_and_get_or_insert() {
-        let mut cache = LruCache::new(2);
-        assert!(cache.is_empty());
+    fn test_resize_by_charge() {
+        let mut cache = LruCache::weighted(10);
 
-        assert_eq!(cache.put("apple", "red"), None);
-        assert_eq!(cache.put("banana", "yellow"), None);
+        cache.put_with_charge(1, "a", 4);
+        cache.put_with_charge(2, "b", 4);
+        cache.put(3, "c");
+
+        cache.resize(5);
 
-        assert_eq!(cache.cap(), 2);
         assert_eq!(cache.len(), 2);
-        assert!(!cache.is_empty());
-        assert_opt_eq(cache.get_or_insert(&"apple", || "orange"), &"red");
-        assert_opt_eq(cache.get_or_insert(&"banana", || "orange"), &"yellow");
-        assert_opt_eq(cache.get_or_insert(&"lemon", || "orange"), &"orange");
-        assert_opt_eq(cache.get_or_insert(&"lemon", || "red"), &"orange");
+        assert_eq!(cache.usage(), 5);
+        assert!(cache.get(&1).is_none());
+        assert_eq!(cache.get(&2), Some(&"b"));
+        assert_eq!(cache.get(&3), Some(&"c"));
     }
 
     #[test]
-    fn test_put_and_get_mut() {
-        let mut cache = LruCache::new(2);
+    fn test_evict_by_epoch() {
+        let mut cache = LruCache::new(4);
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
+        cache.put(1, "a");
+        cache.put(2, "b");
+
+        cache.update_epoch(1);
+
+        cache.put(3, "c");
+        cache.put(4, "d");
+
+        cache.evict_by_epoch(1);
 
-        assert_eq!(cache.cap(), 2);
         assert_eq!(cache.len(), 2);
-        assert_opt_eq_mut(cache.get_mut(&"apple"), "red");
-        assert_opt_eq_mut(cache.get_mut(&"banana"), "yellow");
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&2).is_none());
+        assert_eq!(cache.get(&3), Some(&"c"));
+        assert_eq!(cache.get(&4), Some(&"d"));
+
+        cache.evict_by_epoch(2);
+        assert_eq!(cache.len(), 0);
+        assert!(cache.get(&3).is_none());
+        assert!(cache.get(&4).is_none());
     }
 
     #[test]
-    fn test_get_mut_and_update() {
-        let mut cache = LruCache::new(2);
+    fn test_evict_by_epoch_with_index() {
+        let mut cache = LruCache::new(6);
+        cache.set_protected_ratio(0.5);
+        cache.set_epoch_index(true);
+        assert!(cache.has_epoch_index());
 
-        cache.put("apple", 1);
-        cache.put("banana", 3);
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        cache.get(&1);
+        cache.update_epoch(1);
+        cache.put(4, "d");
+        cache.get(&2);
+        cache.update_epoch(2);
+        cache.put(5, "e");
+        cache.get(&4);
 
-        {
-            let v = cache.get_mut(&"apple").unwrap();
-            *v = 4;
-        }
+        // 3 is stuck behind 5 on probation, and 1 is protected
+        cache.evict_by_epoch(1);
+        let mut keys = cache.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec![2, 4, 5]);
 
-        assert_eq!(cache.cap(), 2);
+        cache.set_epoch_index(false);
+        cache.set_epoch_index(true);
+        cache.evict_by_epoch(2);
+        assert!(!cache.contains(&2));
         assert_eq!(cache.len(), 2);
-        assert_opt_eq_mut(cache.get_mut(&"apple"), 4);
-        assert_opt_eq_mut(cache.get_mut(&"banana"), 3);
+
+        cache.put(6, "f");
+        cache.update_epoch(3);
+        cache.get(&5);
+        cache.evict_by_epoch(3);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), vec![(&5, &"e")]);
+        assert_eq!(cache.pop_lru(), Some((5, "e")));
+        cache.evict_by_epoch(4);
+        assert!(cache.is_empty());
     }
 
     #[test]
-    fn test_put_update() {
-        let mut cache = LruCache::new(1);
+    fn test_drain_by_epoch() {
+        let mut cache = LruCache::new(4);
+        cache.put(1, String::from("a"));
+        cache.put(2, String::from("b"));
+        cache.update_epoch(1);
+        cache.put(3, String::from("c"));
+        cache.update_epoch(2);
+        cache.put(4, String::from("d"));
 
-        assert_eq!(cache.put("apple", "red"), None);
-        assert_eq!(cache.put("apple", "green"), Some("red"));
+        {
+            let mut drain = cache.drain_by_epoch(2);
+            assert_eq!(drain.next(), Some((1, String::from("a"), 0)));
+            assert_eq!(drain.next(), Some((2, String::from("b"), 0)));
+        }
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.peek_lru(), Some((&3, &String::from("c"))));
 
+        let drained: Vec<_> = cache.drain_by_epoch(2).collect();
+        assert_eq!(drained, vec![(3, String::from("c"), 1)]);
+        assert_eq!(cache.drain_by_epoch(2).next(), None);
         assert_eq!(cache.len(), 1);
-        assert_opt_eq(cache.get(&"apple"), "green");
     }
 
     #[test]
-    fn test_put_removes_oldest() {
-        let mut cache = LruCache::new(2);
-
-        assert_eq!(cache.put("apple", "red"), None);
-        assert_eq!(cache.put("banana", "yellow"), None);
-        assert_eq!(cache.put("pear", "green"), None);
+    fn test_epoch_policy() {
+        use super::{Entry, EpochPolicy};
 
-        assert!(cache.get(&"apple").is_none());
-        assert_opt_eq(cache.get(&"banana"), "yellow");
-        assert_opt_eq(cache.get(&"pear"), "green");
+        let mut cache = LruCache::new(3);
+        assert_eq!(cache.epoch_policy(), EpochPolicy::RefreshOnRead);
+        cache.set_epoch_policy(EpochPolicy::NeverRefresh);
+        cache.set_epoch_index(true);
 
-        // Even though we inserted "apple" into the cache earlier it has since been removed from
-        // the cache so there is no current value for `put` to return.
-        assert_eq!(cache.put("apple", "green"), None);
-        assert_eq!(cache.put("tomato", "red"), None);
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.update_epoch(1);
+        cache.put(1, "alpha");
+        cache.get(&2);
+        cache.put(3, "c");
+        assert_eq!(cache.peek_lru(), Some((&1, &"alpha")));
+        cache.evict_by_epoch(1);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), vec![(&3, &"c")]);
 
-        assert!(cache.get(&"pear").is_none());
-        assert_opt_eq(cache.get(&"apple"), "green");
-        assert_opt_eq(cache.get(&"tomato"), "red");
+        cache.set_epoch_policy(EpochPolicy::RefreshOnWrite);
+        cache.put(4, "d");
+        cache.put(7, "g");
+        cache.update_epoch(2);
+        assert_eq!(cache.get_or_insert(3, || "x"), Some(&"c"));
+        *cache.get_mut(&4).unwrap() = "delta";
+        if let Entry::Occupied(mut entry) = cache.peek_entry(7) {
+            assert_eq!(entry.insert("gamma"), "g");
+        }
+        cache.entry(4).or_insert("x");
+        assert_eq!(cache.epoch_of(&3), Some(1));
+        assert_eq!(cache.epoch_of(&4), Some(2));
+        assert_eq!(cache.epoch_of(&7), Some(2));
+        cache.evict_by_epoch(2);
+        assert_eq!(
+            cache.iter().collect::<Vec<_>>(),
+            vec![(&4, &"delta"), (&7, &"gamma")]
+        );
+
+        cache.set_epoch_policy(EpochPolicy::RefreshOnRead);
+        cache.put(5, "e");
+        cache.put(6, "f");
+        cache.update_epoch(3);
+        cache.get(&5);
+        assert_eq!(cache.get_mut_keep_epoch(&6), Some(&mut "f"));
+        cache.evict_by_epoch(3);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), vec![(&5, &"e")]);
     }
 
     #[test]
-    fn test_peek() {
-        let mut cache = LruCache::new(2);
-
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
+    fn test_put_with_epoch() {
+        let mut cache = LruCache::new(3);
+        cache.set_epoch_index(true);
+        cache.update_epoch(10);
 
-        assert_opt_eq(cache.peek(&"banana"), "yellow");
-        assert_opt_eq(cache.peek(&"apple"), "red");
+        assert_eq!(cache.put_with_epoch(1, "a", 2), None);
+        assert_eq!(cache.put_with_epoch(2, "b", 7), None);
+        cache.put(3, "c");
+        assert_eq!(cache.put_with_epoch(2, "beta", 4), Some("b"));
 
-        cache.put("pear", "green");
+        assert_eq!(cache.epoch_of(&1), Some(2));
+        assert_eq!(cache.peek_with_epoch(&2), Some((&"beta", 4)));
+        assert_eq!(cache.epoch_of(&3), Some(10));
+        assert_eq!(cache.peek_lru(), Some((&1, &"a")));
 
-        assert!(cache.peek(&"apple").is_none());
-        assert_opt_eq(cache.peek(&"banana"), "yellow");
-        assert_opt_eq(cache.peek(&"pear"), "green");
+        cache.evict_by_epoch(5);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), vec![(&3, &"c")]);
     }
 
     #[test]
-    fn test_peek_mut() {
-        let mut cache = LruCache::new(2);
+    fn test_epoch_histogram() {
+        use super::EpochUsage;
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
-
-        assert_opt_eq_mut(cache.peek_mut(&"banana"), "yellow");
-        assert_opt_eq_mut(cache.peek_mut(&"apple"), "red");
-        assert!(cache.peek_mut(&"pear").is_none());
+        let mut cache = LruCache::weighted(20);
+        cache.set_epoch_index(true);
+        cache.put_with_charge(1, "a", 2);
+        cache.put_with_charge(2, "b", 3);
+        cache.update_epoch(1);
+        cache.put_with_charge(3, "c", 4);
+        cache.get(&1);
+        cache.put_with_charge(2, "beta", 5);
+        cache.put_with_charge(4, "d", 1);
+        cache.pop(&3);
+
+        let expected = vec![(1, EpochUsage { len: 3, charge: 8 })];
+        assert_eq!(cache.epoch_histogram(), expected);
+        cache.set_epoch_index(false);
+        assert_eq!(cache.epoch_histogram(), expected);
+
+        // the usage is kept up to date without the index too
+        cache.put_with_charge(4, "delta", 2);
+        assert_eq!(
+            cache.epoch_histogram(),
+            vec![(1, EpochUsage { len: 3, charge: 9 })]
+        );
+
+        cache.set_epoch_index(true);
+        cache.update_epoch(2);
+        cache.put_with_charge(5, "e", 6);
+        assert!(cache.pin(&1));
+        cache.evict_by_epoch(2);
+        assert_eq!(
+            cache.epoch_histogram(),
+            vec![
+                (1, EpochUsage { len: 1, charge: 2 }),
+                (2, EpochUsage { len: 1, charge: 6 })
+            ]
+        );
+        cache.clear();
+        assert!(cache.epoch_histogram().is_empty());
+    }
 
-        cache.put("pear", "green");
+    #[test]
+    fn test_custom_epoch_type() {
+        let mut cache = LruCache::with_epoch(3, 10u32);
+        cache.set_epoch_index(true);
+        cache.put(1, "a");
+        cache.update_epoch(11);
+        cache.put(2, "b");
+        cache.put_with_epoch(3, "c", 5);
+        cache.update_epoch(12);
+
+        assert_eq!(cache.current_epoch(), 12);
+        assert_eq!(
+            cache.drain_by_epoch(11).collect::<Vec<_>>(),
+            vec![(1, "a", 10)]
+        );
+        cache.evict_by_epoch(11);
+        assert_eq!(cache.pop_lru(), Some((2, "b")));
+        assert!(cache.is_empty());
+    }
 
-        assert!(cache.peek_mut(&"apple").is_none());
-        assert_opt_eq_mut(cache.peek_mut(&"banana"), "yellow");
-        assert_opt_eq_mut(cache.peek_mut(&"pear"), "green");
+    #[test]
+    fn test_epoch_type_without_default() {
+        use std::time::{Duration, Instant};
 
-        {
-            let v = cache.peek_mut(&"banana").unwrap();
-            *v = "green";
-        }
+        // an epoch with no meaningful zero
+        let start = Instant::now();
+        let mut cache = LruCache::unbounded_with_epoch(start);
+        cache.put(1, "a");
+        cache.update_epoch(start + Duration::from_secs(1));
+        cache.put(2, "b");
 
-        assert_opt_eq_mut(cache.peek_mut(&"banana"), "green");
+        assert_eq!(cache.epoch_of(&1), Some(start));
+        cache.evict_by_epoch(start + Duration::from_secs(1));
+        assert_eq!(cache.pop_lru(), Some((2, "b")));
+        assert!(cache.is_empty());
     }
 
     #[test]
-    fn test_peek_lru() {
-        let mut cache = LruCache::new(2);
+    fn test_pinned_entries_are_not_evicted() {
+        use super::RemovalCause;
+        use std::sync::{Arc, Mutex};
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let mut cache = LruCache::weighted(4);
+        let log = removed.clone();
+        cache.set_eviction_listener(move |k: &i32, _: &&str, cause| {
+            log.lock().unwrap().push((*k, cause))
+        });
+        cache.set_epoch_index(true);
 
-        assert!(cache.peek_lru().is_none());
+        cache.put(1, "a");
+        cache.put(2, "b");
+        assert!(cache.pin(&1));
+        assert!(cache.pin(&1));
+        assert!(!cache.pin(&5));
+        assert_eq!(cache.pinned_len(), 1);
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
-        assert_opt_eq_tuple(cache.peek_lru(), ("apple", "red"));
+        cache.put_with_charge(3, "c", 3);
+        assert_eq!(*removed.lock().unwrap(), vec![(2, RemovalCause::Capacity)]);
+        assert_eq!(cache.peek_lru(), Some((&3, &"c")));
 
-        cache.get(&"apple");
-        assert_opt_eq_tuple(cache.peek_lru(), ("banana", "yellow"));
+        cache.update_epoch(1);
+        cache.evict_by_epoch(1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.is_pinned(&1));
+        assert_eq!(cache.epoch_of(&1), Some(0));
+
+        cache.put_with_charge(4, "d", 4);
+        assert!(cache.is_overcommitted());
+        assert_eq!(cache.len(), 2);
+        cache.resize(0);
+        assert!(cache.is_overcommitted());
+        assert_eq!(cache.iter().collect::<Vec<_>>(), vec![(&1, &"a")]);
+
+        assert!(cache.unpin(&1));
+        assert!(cache.is_overcommitted());
+        assert!(cache.unpin(&1));
+        assert!(!cache.unpin(&1));
+        assert!(!cache.is_overcommitted());
+        assert!(cache.is_empty());
+        assert_eq!(cache.pinned_len(), 0);
 
+        cache.resize(4);
+        cache.put(5, "e");
+        cache.pin(&5);
         cache.clear();
-        assert!(cache.peek_lru().is_none());
+        assert!(cache.is_empty());
+        assert_eq!(cache.pinned_len(), 0);
     }
 
     #[test]
-    fn test_contains() {
-        let mut cache = LruCache::new(2);
+    #[cfg(feature = "allocator-api")]
+    fn test_try_put_with_a_bounded_allocator() {
+        use super::TryReserveError;
+        use allocator_api2::alloc::{AllocError, Allocator, Global};
+        use std::alloc::Layout;
+        use std::cell::Cell;
+        use std::ptr::NonNull;
+        use std::rc::Rc;
+
+        // allows as many allocations as are left in its budget
+        #[derive(Clone)]
+        struct Budget(Rc<Cell<usize>>);
+
+        unsafe impl Allocator for Budget {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                if self.0.get() == 0 {
+                    return Err(AllocError);
+                }
+                self.0.set(self.0.get() - 1);
+                Global.allocate(layout)
+            }
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
-        cache.put("pear", "green");
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                Global.deallocate(ptr, layout)
+            }
+        }
 
-        assert!(!cache.contains(&"apple"));
-        assert!(cache.contains(&"banana"));
-        assert!(cache.contains(&"pear"));
+        let budget = Rc::new(Cell::new(usize::MAX));
+        let mut cache = LruCache::new_in(4, Budget(budget.clone()));
+        cache.try_reserve(2).unwrap();
+
+        budget.set(0);
+        assert_eq!(cache.try_put(1, "a"), Ok(None));
+        assert_eq!(cache.try_push(2, "b"), Ok(None));
+        assert!(matches!(
+            cache.try_put(3, "c"),
+            Err(TryReserveError::AllocError { .. })
+        ));
+        assert_eq!(cache.try_put(1, "alpha"), Ok(Some("a")));
+        assert_eq!(cache.try_get_or_insert(2, || "beta"), Ok(Some(&"b")));
+        assert!(cache.try_get_or_insert(4, || "d").is_err());
+        assert_eq!(cache.len(), 2);
+
+        budget.set(1);
+        assert_eq!(cache.try_get_or_insert(4, || "d"), Ok(Some(&"d")));
+        assert_eq!(
+            cache.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
     }
 
     #[test]
-    fn test_pop() {
-        let mut cache = LruCache::new(2);
+    #[cfg(feature = "allocator-api")]
+    fn test_try_put_into_a_full_cache_with_an_exhausted_allocator() {
+        use allocator_api2::alloc::{AllocError, Allocator, Global};
+        use std::alloc::Layout;
+        use std::cell::Cell;
+        use std::ptr::NonNull;
+        use std::rc::Rc;
+
+        // allows as many allocations as are left in its budget
+        #[derive(Clone)]
+        struct Budget(Rc<Cell<usize>>);
+
+        unsafe impl Allocator for Budget {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                if self.0.get() == 0 {
+                    return Err(AllocError);
+                }
+                self.0.set(self.0.get() - 1);
+                Global.allocate(layout)
+            }
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                Global.deallocate(ptr, layout)
+            }
+        }
 
+        let budget = Rc::new(Cell::new(usize::MAX));
+        let mut cache = LruCache::new_in(2, Budget(budget.clone()));
+        cache.put(1, 1);
+        cache.put(2, 2);
+
+        // a full cache recycles the evicted node instead of allocating a new one
+        budget.set(0);
+        assert_eq!(cache.try_put(3, 3), Ok(None));
+        assert_eq!(cache.try_push(4, 4), Ok(Some((2, 2))));
+        assert_eq!(cache.try_get_or_insert(5, || 5), Ok(Some(&5)));
         assert_eq!(cache.len(), 2);
-        assert_opt_eq(cache.get(&"apple"), "red");
-        assert_opt_eq(cache.get(&"banana"), "yellow");
+        assert_eq!(cache.peek(&4), Some(&4));
+        assert_eq!(cache.peek(&5), Some(&5));
 
-        let popped = cache.pop(&"apple");
-        assert!(popped.is_some());
-        assert_eq!(popped.unwrap(), "red");
+        // once an entry is gone there is no node to recycle
+        cache.pop(&4);
+        assert!(cache.try_put(6, 6).is_err());
         assert_eq!(cache.len(), 1);
-        assert!(cache.get(&"apple").is_none());
-        assert_opt_eq(cache.get(&"banana"), "yellow");
     }
 
     #[test]
-    fn test_pop_entry() {
-        let mut cache = LruCache::new(2);
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
-
-        assert_eq!(cache.len(), 2);
-        assert_opt_eq(cache.get(&"apple"), "red");
-        assert_opt_eq(cache.get(&"banana"), "yellow");
-
-        let popped = cache.pop_entry(&"apple");
-        assert!(popped.is_some());
-        assert_eq!(popped.unwrap(), ("apple", "red"));
-        assert_eq!(cache.len(), 1);
-        assert!(cache.get(&"apple").is_none());
-        assert_opt_eq(cache.get(&"banana"), "yellow");
+    fn test_memory_usage() {
+        use super::{EpochUsage, LruEntry};
+        use std::mem;
+
+        let node = mem::size_of::<LruEntry<u64, String, u64>>();
+        let epoch = mem::size_of::<(u64, EpochUsage)>();
+        let mut cache: LruCache<u64, String> = LruCache::new(4);
+        let empty = cache.memory_usage();
+        assert!(empty >= 2 * node);
+
+        // both entries share the one epoch
+        cache.put(1, String::from("apple"));
+        cache.put(2, String::with_capacity(100));
+        assert_eq!(cache.memory_usage(), empty + 2 * node + epoch);
+        assert_eq!(cache.deep_memory_usage(), empty + 2 * node + epoch + 105);
+
+        cache.set_protected_ratio(0.5);
+        assert_eq!(cache.memory_usage(), empty + 3 * node + epoch);
+        cache.try_reserve(3).unwrap();
+        assert_eq!(cache.memory_usage(), empty + 6 * node + epoch);
     }
 
     #[test]
-    fn test_pop_lru() {
-        let mut cache = LruCache::new(200);
+    fn test_retain_and_extract_if() {
+        use super::{EpochUsage, RemovalCause};
+        use std::sync::{Arc, Mutex};
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let mut cache = LruCache::new(6);
+        let log = removed.clone();
+        cache.set_eviction_listener(move |k: &i32, _: &i32, cause| {
+            log.lock().unwrap().push((*k, cause))
+        });
+        cache.set_protected_ratio(0.5);
+        cache.set_epoch_index(true);
 
-        for i in 0..75 {
-            cache.put(i, "A");
-        }
-        for i in 0..75 {
-            cache.put(i + 100, "B");
-        }
-        for i in 0..75 {
-            cache.put(i + 200, "C");
+        for i in 1..=6 {
+            cache.put(i, i * 10);
         }
-        assert_eq!(cache.len(), 200);
+        cache.get(&2);
+        assert!(cache.pin(&4));
 
-        for i in 0..75 {
-            assert_opt_eq(cache.get(&(74 - i + 100)), "B");
-        }
-        assert_opt_eq(cache.get(&25), "A");
+        cache.retain(|k, v| {
+            *v += 1;
+            k % 2 == 1
+        });
+        assert_eq!(
+            *removed.lock().unwrap(),
+            vec![
+                (2, RemovalCause::Explicit),
+                (6, RemovalCause::Explicit),
+                (4, RemovalCause::Explicit)
+            ]
+        );
+        assert_eq!(cache.pinned_len(), 0);
+        assert_eq!(
+            cache.iter().collect::<Vec<_>>(),
+            vec![(&5, &51), (&3, &31), (&1, &11)]
+        );
+        assert_eq!(
+            cache.epoch_histogram(),
+            vec![(0, EpochUsage { len: 3, charge: 3 })]
+        );
 
-        for i in 26..75 {
-            assert_eq!(cache.pop_lru(), Some((i, "A")));
-        }
-        for i in 0..75 {
-            assert_eq!(cache.pop_lru(), Some((i + 200, "C")));
+        {
+            let mut extract = cache.extract_if(|_, v| *v > 20);
+            assert_eq!(extract.next(), Some((5, 51)));
         }
-        for i in 0..75 {
-            assert_eq!(cache.pop_lru(), Some((74 - i + 100, "B")));
+        assert_eq!(cache.len(), 2);
+
+        assert_eq!(cache.extract_if(|_, _| true).count(), 2);
+        assert!(cache.is_empty());
+        assert_eq!(cache.epoch_histogram(), vec![]);
+    }
+
+    #[test]
+    fn test_cursor_walks_past_the_sigils() {
+        let mut cache = LruCache::new(4);
+        cache.set_protected_ratio(0.5);
+        for i in 1..=4 {
+            cache.put(i, i * 10);
         }
-        assert_eq!(cache.pop_lru(), Some((25, "A")));
-        for _ in 0..50 {
-            assert_eq!(cache.pop_lru(), None);
+        cache.get(&3);
+        cache.update_epoch(1);
+        cache.get(&1);
+
+        let mut seen = Vec::new();
+        let mut cursor = cache.cursor_mru();
+        while let Some((&k, v)) = cursor.current() {
+            *v += 1;
+            seen.push((k, cursor.epoch().unwrap()));
+            cursor.move_next();
         }
+        assert_eq!(seen, vec![(1, 1), (3, 0), (4, 0), (2, 0)]);
+
+        cursor.move_prev();
+        assert_eq!(cursor.key(), Some(&2));
+        cursor.move_prev();
+        assert_eq!(cursor.remove_current(), Some((4, 41)));
+        assert_eq!(cursor.key(), Some(&2));
+        cursor.move_next();
+        assert!(cursor.is_ghost());
+        assert_eq!(cursor.remove_current(), None);
+
+        cursor.move_next();
+        cursor.move_current_to_lru();
+        assert_eq!(cursor.key(), Some(&1));
+        cursor.move_prev();
+        assert_eq!(cursor.key(), Some(&2));
+        cursor.move_current_to_mru();
+        cursor.move_next();
+        assert_eq!(cursor.key(), Some(&3));
+
+        assert_eq!(cache.peek_lru(), Some((&1, &11)));
+        cache.put(5, 50);
+        cache.put(6, 60);
+        assert_eq!(
+            cache.iter().collect::<Vec<_>>(),
+            vec![(&2, &21), (&3, &31), (&6, &60), (&5, &50)]
+        );
     }
 
     #[test]
-    fn test_clear() {
-        let mut cache = LruCache::new(2);
-
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
-
-        assert_eq!(cache.len(), 2);
-        assert_opt_eq(cache.get(&"apple"), "red");
-        assert_opt_eq(cache.get(&"banana"), "yellow");
-
-        cache.clear();
-        assert_eq!(cache.len(), 0);
+    fn test_reposition_across_segments() {
+        let mut cache = LruCache::new(4);
+        cache.set_protected_ratio(0.5);
+        for i in 1..=4 {
+            cache.put(i, i);
+        }
+        cache.update_epoch(1);
+        assert!(cache.promote(&1));
+        assert!(cache.promote(&2));
+        assert_eq!(cache.epoch_of(&1), Some(1));
+
+        // moving 4 in front of 1 fills the protected segment over its share, which pushes 1
+        // back to the head of probation
+        assert!(cache.move_before(&4, &1));
+        assert!(!cache.move_before(&4, &5));
+        assert!(cache.move_after(&2, &3));
+        assert!(cache.move_after(&2, &2));
+        assert_eq!(
+            cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![4, 1, 3, 2]
+        );
+        assert_eq!(cache.epoch_of(&4), Some(0));
+
+        assert!(cache.demote(&4));
+        assert!(!cache.demote(&5));
+        cache.put(5, 5);
+        cache.put(6, 6);
+        assert!(!cache.contains(&4));
+        assert!(!cache.contains(&2));
+        assert_eq!(
+            cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![6, 5, 1, 3]
+        );
     }
 
     #[test]
-    fn test_resize_larger() {
-        let mut cache = LruCache::new(2);
-
-        cache.put(1, "a");
-        cache.put(2, "b");
-        cache.resize(4);
-        cache.put(3, "c");
-        cache.put(4, "d");
+    fn test_reposition_into_the_window() {
+        let mut cache = LruCache::new(4);
+        cache.set_admission_window(Some(0.25));
+        for i in 1..=4 {
+            cache.put(i, i);
+        }
+        assert_eq!(cache.window_usage, 1);
 
+        // the window only has room for 3, so 4 moves on to probation
+        assert!(cache.move_before(&3, &4));
+        assert_eq!(cache.window_usage, 1);
         assert_eq!(cache.len(), 4);
-        assert_eq!(cache.get(&1), Some(&"a"));
-        assert_eq!(cache.get(&2), Some(&"b"));
-        assert_eq!(cache.get(&3), Some(&"c"));
-        assert_eq!(cache.get(&4), Some(&"d"));
+        assert_eq!(
+            cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![3, 4, 2, 1]
+        );
     }
 
     #[test]
-    fn test_resize_smaller() {
+    fn test_cold_insertion_does_not_displace_hot_entries() {
         let mut cache = LruCache::new(4);
+        cache.set_admission_window(Some(0.25));
+        for i in 1..=3 {
+            cache.put(i, "hot");
+            cache.get(&i);
+        }
 
-        cache.put(1, "a");
-        cache.put(2, "b");
-        cache.put(3, "c");
-        cache.put(4, "d");
+        // a scan churns through a single slot at the tail
+        for i in 100..110 {
+            cache.put_cold(i, "scan");
+        }
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.peek_lru(), Some((&109, &"scan")));
+        for i in 1..=3 {
+            assert!(cache.contains(&i));
+        }
 
-        cache.resize(2);
+        // an existing entry keeps its position but takes the current epoch
+        cache.update_epoch(1);
+        assert_eq!(cache.push_cold(2, "warm"), Some((2, "hot")));
+        assert_eq!(cache.epoch_of(&2), Some(1));
+        assert_eq!(cache.peek_lru(), Some((&109, &"scan")));
 
-        assert_eq!(cache.len(), 2);
-        assert!(cache.get(&1).is_none());
-        assert!(cache.get(&2).is_none());
-        assert_eq!(cache.get(&3), Some(&"c"));
-        assert_eq!(cache.get(&4), Some(&"d"));
+        assert_eq!(cache.get(&109), Some(&"scan"));
+        assert_eq!(cache.push_cold(200, "scan"), Some((1, "hot")));
     }
 
     #[test]
-    fn test_evict_by_epoch() {
+    fn test_clone_keeps_segments_epochs_and_pins() {
         let mut cache = LruCache::new(4);
-
-        cache.put(1, "a");
-        cache.put(2, "b");
-
+        cache.set_admission_window(Some(0.25));
+        cache.set_epoch_index(true);
+        for i in 1..=4 {
+            cache.put(i, i.to_string());
+        }
         cache.update_epoch(1);
+        cache.get(&2);
+        cache.pin(&2);
+        cache.pin(&3);
+        cache.resize(1);
+        assert!(cache.is_overcommitted());
+
+        let mut copy = cache.clone();
+        assert_eq!(copy, cache);
+        assert_eq!(copy.cap(), 1);
+        assert_eq!(copy.usage(), 2);
+        assert_eq!(copy.pinned_len(), 2);
+        assert!(copy.is_overcommitted());
+        assert_eq!(copy.epoch_of(&2), Some(1));
+        assert_eq!(copy.epoch_histogram(), cache.epoch_histogram());
+
+        // both evolve the same way from here
+        for c in [&mut cache, &mut copy] {
+            c.resize(4);
+            c.put(5, "5".to_string());
+            c.get(&5);
+            c.put(6, "6".to_string());
+            c.unpin(&3);
+            c.evict_by_epoch(1);
+        }
+        assert_eq!(copy, cache);
+        assert_eq!(copy.epoch_histogram(), cache.epoch_histogram());
 
-        cache.put(3, "c");
-        cache.put(4, "d");
-
-        cache.evict_by_epoch(1);
-
-        assert_eq!(cache.len(), 2);
-        assert!(cache.get(&1).is_none());
-        assert!(cache.get(&2).is_none());
-        assert_eq!(cache.get(&3), Some(&"c"));
-        assert_eq!(cache.get(&4), Some(&"d"));
+        copy.put(7, "7".to_string());
+        assert_ne!(copy, cache);
+    }
 
-        cache.evict_by_epoch(2);
-        assert_eq!(cache.len(), 0);
-        assert!(cache.get(&3).is_none());
-        assert!(cache.get(&4).is_none());
+    #[test]
+    fn test_extend_from_iter_and_debug() {
+        let mut cache: LruCache<i32, &str> = vec![(1, "a"), (2, "b")].into_iter().collect();
+        assert_eq!(cache.cap(), usize::MAX);
+        assert_eq!(cache.peek_lru(), Some((&1, &"a")));
+
+        cache.resize(3);
+        cache.extend(vec![(3, "c"), (1, "alpha"), (4, "d")]);
+        assert_eq!(format!("{:?}", cache), "LruCache { len: 3, cap: 3 }");
+        assert_eq!(
+            format!("{:?}", cache.iter()),
+            r#"[(4, "d"), (1, "alpha"), (3, "c")]"#
+        );
+
+        // the cache itself formats even if its values do not implement `Debug`
+        struct Opaque;
+        let mut cache = LruCache::with_hasher(2, super::DefaultHasher::default());
+        cache.put(1, Opaque);
+        assert_eq!(format!("{:?}", cache), "LruCache { len: 1, cap: 2 }");
     }
 
     #[test]
@@ -1897,6 +5882,25 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn test_into_iter_yields_pinned_entries() {
+        let mut cache = LruCache::new(3);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+        cache.pin(&"a");
+        cache.pin(&"c");
+
+        assert_eq!(
+            cache.clone().into_iter().count(),
+            cache.clone().into_iter().collect::<Vec<_>>().len()
+        );
+        assert_eq!(
+            cache.into_iter().collect::<Vec<_>>(),
+            vec![("a", 1), ("b", 2), ("c", 3)]
+        );
+    }
+
     #[test]
     fn test_that_pop_actually_detaches_node() {
         let mut cache = LruCache::new(5);
@@ -2017,6 +6021,7 @@ mod tests {
         static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
 
         #[derive(Hash, Eq)]
+        #[allow(clippy::derived_hash_with_manual_eq)]
         struct KeyDropCounter(usize);
 
         impl PartialEq for KeyDropCounter {
@@ -2049,4 +6054,89 @@ mod tests {
         let mut cache = LruCache::new(0);
         cache.put("reizeiin", "tohka");
     }
+
+    #[test]
+    fn test_eviction_listener() {
+        use super::RemovalCause::{self, *};
+        use std::sync::{Arc, Mutex};
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let mut cache = LruCache::new(3);
+        {
+            let removed = removed.clone();
+            cache.set_eviction_listener(move |k: &i32, v: &&'static str, cause: RemovalCause| {
+                removed.lock().unwrap().push((*k, *v, cause));
+            });
+        }
+        let take = || mem::take(&mut *removed.lock().unwrap());
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        cache.put(4, "d");
+        assert_eq!(take(), vec![(1, "a", Capacity)]);
+
+        cache.put(3, "e");
+        assert_eq!(take(), vec![(3, "c", Replaced)]);
+
+        assert_eq!(cache.push(5, "f"), Some((2, "b")));
+        assert_eq!(take(), vec![(2, "b", Capacity)]);
+
+        cache.pop(&4);
+        cache.pop_lru();
+        assert_eq!(take(), vec![(4, "d", Explicit), (3, "e", Explicit)]);
+
+        cache.update_epoch(1);
+        cache.put(6, "g");
+        cache.put(7, "h");
+        cache.evict_by_epoch(1);
+        assert_eq!(take(), vec![(5, "f", Epoch)]);
+
+        cache.resize(1);
+        assert_eq!(take(), vec![(6, "g", Resize)]);
+
+        cache.clear();
+        assert_eq!(take(), vec![(7, "h", Clear)]);
+
+        cache.put(8, "i");
+        drop(cache);
+        assert_eq!(take(), vec![(8, "i", Clear)]);
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn test_stats() {
+        use super::CacheStats;
+
+        let mut cache = LruCache::new(2);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(1, "c");
+        cache.put(3, "d");
+        cache.get(&1);
+        cache.get_mut(&2);
+        cache.get_or_insert(4, || "e");
+        cache.pop(&4);
+        cache.update_epoch(1);
+        cache.evict_by_epoch(1);
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                inserts: 4,
+                updates: 1,
+                capacity_evictions: 2,
+                epoch_evictions: 1,
+                explicit_pops: 1,
+                expirations: 0,
+                rejections: 0,
+            }
+        );
+
+        cache.reset_stats();
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
 }
@@ -0,0 +1,234 @@
+// MIT License
+
+// Copyright (c) 2016 Jerome Froelich
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! `Serialize` and `Deserialize` implementations for `LruCache`, enabled by the `serde` feature.
+//!
+//! A cache is written as a struct with its `cap`, its `cur_epoch` and its `entries`, a
+//! sequence of `(key, value, epoch, charge)` tuples in least to most recently used order.
+//! Expired entries are left out. Pins are not written, so entries that only fit thanks to
+//! them are evicted again, least recently used first, when the cache is read back.
+
+#[cfg(feature = "allocator-api")]
+use allocator_api2::alloc::Allocator;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use hashbrown::HashMap;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, SerializeStruct, Serializer};
+
+use crate::{Epoch, LruCache, LruEntry};
+
+const FIELDS: &[&str] = &["cap", "cur_epoch", "entries"];
+
+impl<K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> Serialize for with_alloc!(LruCache<K, V, S, E>)
+where
+    K: Hash + Eq + Serialize,
+    V: Serialize,
+    S: BuildHasher,
+    E: Epoch + Serialize,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        let mut state = serializer.serialize_struct("LruCache", FIELDS.len())?;
+        state.serialize_field("cap", &self.cap)?;
+        state.serialize_field("cur_epoch", &self.cur_epoch)?;
+        state.serialize_field("entries", &Entries(self))?;
+        state.end()
+    }
+}
+
+// Serializes the entries of a cache from the least to the most recently used one.
+struct Entries<'a, K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator>(
+    &'a with_alloc!(LruCache<K, V, S, E>),
+);
+
+impl<'a, K, V, S, E, #[cfg(feature = "allocator-api")] A: Clone + Allocator> Serialize
+    for with_alloc!(Entries<'a, K, V, S, E>)
+where
+    K: Hash + Eq + Serialize,
+    V: Serialize,
+    S: BuildHasher,
+    E: Epoch + Serialize,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        let cache = self.0;
+        let mut live = Vec::with_capacity(cache.len());
+        let mut node: *const LruEntry<K, V, E> = unsafe { (*cache.tail).prev };
+        while node != cache.head {
+            if node != cache.mid && node != cache.win && !cache.is_expired(node) {
+                live.push(node);
+            }
+            node = unsafe { (*node).prev };
+        }
+
+        let mut seq = serializer.serialize_seq(Some(live.len()))?;
+        for node in live {
+            unsafe {
+                seq.serialize_element(&(
+                    &*(*node).key.as_ptr(),
+                    &*(*node).val.as_ptr(),
+                    (*node).epoch,
+                    (*node).charge,
+                ))?;
+            }
+        }
+        seq.end()
+    }
+}
+
+impl<'de, K, V, S, E> Deserialize<'de> for LruCache<K, V, S, E>
+where
+    K: Hash + Eq + Deserialize<'de>,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+    E: Epoch + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("LruCache", FIELDS, CacheVisitor(PhantomData))
+    }
+}
+
+enum Field {
+    Cap,
+    CurEpoch,
+    Entries,
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldVisitor;
+
+        impl<'de> Visitor<'de> for FieldVisitor {
+            type Value = Field;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("`cap`, `cur_epoch` or `entries`")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Field, E> {
+                match value {
+                    "cap" => Ok(Field::Cap),
+                    "cur_epoch" => Ok(Field::CurEpoch),
+                    "entries" => Ok(Field::Entries),
+                    _ => Err(de::Error::unknown_field(value, FIELDS)),
+                }
+            }
+        }
+
+        deserializer.deserialize_identifier(FieldVisitor)
+    }
+}
+
+type RawEntry<K, V, E> = (K, V, E, usize);
+
+struct CacheVisitor<K, V, S, E>(PhantomData<LruCache<K, V, S, E>>);
+
+impl<'de, K, V, S, E> Visitor<'de> for CacheVisitor<K, V, S, E>
+where
+    K: Hash + Eq + Deserialize<'de>,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+    E: Epoch + Deserialize<'de>,
+{
+    type Value = LruCache<K, V, S, E>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("struct LruCache")
+    }
+
+    fn visit_seq<T: SeqAccess<'de>>(self, mut seq: T) -> Result<Self::Value, T::Error> {
+        let cap = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let cur_epoch = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let entries = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        rebuild(cap, cur_epoch, entries)
+    }
+
+    fn visit_map<T: MapAccess<'de>>(self, mut map: T) -> Result<Self::Value, T::Error> {
+        let mut cap = None;
+        let mut cur_epoch = None;
+        let mut entries = None;
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Cap => {
+                    if cap.is_some() {
+                        return Err(de::Error::duplicate_field("cap"));
+                    }
+                    cap = Some(map.next_value()?);
+                }
+                Field::CurEpoch => {
+                    if cur_epoch.is_some() {
+                        return Err(de::Error::duplicate_field("cur_epoch"));
+                    }
+                    cur_epoch = Some(map.next_value()?);
+                }
+                Field::Entries => {
+                    if entries.is_some() {
+                        return Err(de::Error::duplicate_field("entries"));
+                    }
+                    entries = Some(map.next_value()?);
+                }
+            }
+        }
+        let cap = cap.ok_or_else(|| de::Error::missing_field("cap"))?;
+        let cur_epoch = cur_epoch.ok_or_else(|| de::Error::missing_field("cur_epoch"))?;
+        let entries = entries.ok_or_else(|| de::Error::missing_field("entries"))?;
+        rebuild(cap, cur_epoch, entries)
+    }
+}
+
+// Inserts the entries with their epochs from the least to the most recently used one, so that
+// the last one ends up at the head of the list. Entries beyond the capacity are evicted as they
+// would be by `put`.
+fn rebuild<K, V, S, E, Er>(
+    cap: usize,
+    cur_epoch: E,
+    entries: Vec<RawEntry<K, V, E>>,
+) -> Result<LruCache<K, V, S, E>, Er>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+    E: Epoch,
+    Er: de::Error,
+{
+    if entries.iter().any(|e| e.2 > cur_epoch) {
+        return Err(Er::custom("an entry epoch is ahead of the current epoch"));
+    }
+
+    let map = HashMap::with_capacity_and_hasher(entries.len(), S::default());
+    let mut cache = LruCache::construct(cap, map, cur_epoch);
+    for (key, val, epoch, charge) in entries {
+        if cache
+            .capturing_put(key, val, Some(charge), None, Some(epoch), false, false)
+            .is_some()
+        {
+            return Err(Er::custom("duplicate key in entries"));
+        }
+    }
+    Ok(cache)
+}
@@ -0,0 +1,784 @@
+// MIT License
+
+// Copyright (c) 2016 Jerome Froelich
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A concurrent LRU cache that splits its entries over several independently locked
+//! [`LruCache`] shards.
+//!
+//! [`LruCache`]: ../struct.LruCache.html
+
+use alloc::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Mutex, MutexGuard};
+
+#[cfg(feature = "stats")]
+use crate::CacheStats;
+use crate::{DefaultHasher, Epoch, EpochUsage, HeapSize, LruCache};
+
+type Shards<K, V, S, E> = Box<[Mutex<LruCache<K, V, S, E>>]>;
+
+/// A concurrent LRU cache made of `N` [`LruCache`] shards, each behind its own lock.
+///
+/// Keys are assigned to shards by hashing them with the cache's `BuildHasher`, so operations on
+/// keys in different shards do not contend with each other. Recency is tracked per shard, which
+/// means an entry is evicted once it is the least recently used entry of its own shard. All
+/// shards follow the same epoch, which may be of any [`Epoch`] type.
+///
+/// # Example
+///
+/// ```
+/// use lru::ShardedLruCache;
+///
+/// let cache = ShardedLruCache::new(1024, 8);
+/// cache.put("apple", 3);
+/// cache.put("banana", 2);
+///
+/// assert_eq!(cache.get(&"apple"), Some(3));
+/// assert_eq!(cache.pop(&"banana"), Some(2));
+/// assert_eq!(cache.len(), 1);
+/// ```
+///
+/// [`LruCache`]: struct.LruCache.html
+/// [`Epoch`]: trait.Epoch.html
+pub struct ShardedLruCache<K, V, S = DefaultHasher, E = u64> {
+    shards: Shards<K, V, S, E>,
+    hash_builder: S,
+
+    // serializes epoch updates so that every shard observes them in order
+    cur_epoch: Mutex<E>,
+}
+
+impl<K: Hash + Eq, V> ShardedLruCache<K, V> {
+    /// Creates a new sharded LRU Cache that holds at most `cap` items in total, spread over
+    /// `num_shards` shards. A capacity below `num_shards` reduces the number of shards, see
+    /// [`with_hasher`]. Room for `cap` items is reserved up front, so use [`weighted`] if
+    /// entries are put with charges.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::ShardedLruCache;
+    /// let cache: ShardedLruCache<isize, &str> = ShardedLruCache::new(10, 4);
+    /// ```
+    ///
+    /// [`with_hasher`]: #method.with_hasher
+    /// [`weighted`]: #method.weighted
+    pub fn new(cap: usize, num_shards: usize) -> ShardedLruCache<K, V> {
+        ShardedLruCache::with_hasher(cap, num_shards, DefaultHasher::default())
+    }
+
+    /// Creates a new sharded LRU Cache whose entries have a total charge of at most `cap`,
+    /// spread over `num_shards` shards. Unlike `new`, no space is reserved up front since `cap`
+    /// does not bound the number of entries. This is the constructor to use with
+    /// [`put_with_charge`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::ShardedLruCache;
+    /// let cache: ShardedLruCache<isize, Vec<u8>> = ShardedLruCache::weighted(64 << 20, 8);
+    /// cache.put_with_charge(1, vec![0; 1024], 1024);
+    /// assert_eq!(cache.usage(), 1024);
+    /// ```
+    ///
+    /// [`put_with_charge`]: #method.put_with_charge
+    pub fn weighted(cap: usize, num_shards: usize) -> ShardedLruCache<K, V> {
+        ShardedLruCache::weighted_with_hasher(cap, num_shards, DefaultHasher::default())
+    }
+
+    /// Creates a new sharded LRU Cache that never automatically evicts items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::ShardedLruCache;
+    /// let cache: ShardedLruCache<isize, &str> = ShardedLruCache::unbounded(4);
+    /// ```
+    pub fn unbounded(num_shards: usize) -> ShardedLruCache<K, V> {
+        ShardedLruCache::unbounded_with_hasher(num_shards, DefaultHasher::default())
+    }
+}
+
+impl<K: Hash + Eq, V, E: Epoch> ShardedLruCache<K, V, DefaultHasher, E> {
+    /// Creates a new sharded LRU Cache that holds at most `cap` items in total, spread over
+    /// `num_shards` shards, and that starts in the given epoch. See [`new`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::ShardedLruCache;
+    ///
+    /// let cache = ShardedLruCache::with_epoch(8, 2, (1_700_000_000u64, 0u32));
+    /// cache.put(1, "a");
+    /// cache.update_epoch((1_700_000_000, 1));
+    /// cache.evict_by_epoch((1_700_000_000, 1));
+    /// assert!(!cache.contains(&1));
+    /// ```
+    ///
+    /// [`new`]: #method.new
+    pub fn with_epoch(cap: usize, num_shards: usize, epoch: E) -> Self {
+        ShardedLruCache::split(
+            cap,
+            num_shards,
+            DefaultHasher::default(),
+            epoch,
+            |share, _| LruCache::with_epoch(share, epoch),
+        )
+    }
+
+    /// Creates a new sharded LRU Cache that never automatically evicts items and starts in the
+    /// given epoch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::ShardedLruCache;
+    /// let cache: ShardedLruCache<isize, &str, _, u32> =
+    ///     ShardedLruCache::unbounded_with_epoch(4, 7);
+    /// assert_eq!(cache.current_epoch(), 7);
+    /// ```
+    pub fn unbounded_with_epoch(num_shards: usize, epoch: E) -> Self {
+        assert!(num_shards > 0, "a sharded cache needs at least one shard");
+        let shards = (0..num_shards)
+            .map(|_| Mutex::new(LruCache::unbounded_with_epoch(epoch)))
+            .collect();
+        ShardedLruCache::construct(shards, DefaultHasher::default(), epoch)
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> ShardedLruCache<K, V, S> {
+    /// Creates a new sharded LRU Cache that holds at most `cap` items in total and uses the
+    /// provided hash builder both to pick a shard and to hash keys within it. Each shard gets
+    /// an equal share of the capacity.
+    ///
+    /// The number of shards is capped at `cap`, with at least one shard, so that every shard
+    /// can hold at least one item. Otherwise keys that hash to a shard without capacity would
+    /// never be cached. Each shard reserves room for its share up front; see
+    /// [`weighted_with_hasher`] for a capacity that is a total charge.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{DefaultHasher, ShardedLruCache};
+    ///
+    /// let s = DefaultHasher::default();
+    /// let cache: ShardedLruCache<isize, &str> = ShardedLruCache::with_hasher(10, 4, s);
+    /// assert_eq!(cache.cap(), 10);
+    ///
+    /// let s = DefaultHasher::default();
+    /// let cache: ShardedLruCache<isize, &str> = ShardedLruCache::with_hasher(2, 8, s);
+    /// assert_eq!(cache.num_shards(), 2);
+    /// ```
+    ///
+    /// [`weighted_with_hasher`]: #method.weighted_with_hasher
+    pub fn with_hasher(cap: usize, num_shards: usize, hash_builder: S) -> Self {
+        ShardedLruCache::split(cap, num_shards, hash_builder, 0, LruCache::with_hasher)
+    }
+
+    /// Creates a new sharded LRU Cache whose entries have a total charge of at most `cap` and
+    /// that uses the provided hash builder to hash keys. The charge is split over the shards
+    /// like the capacity of [`with_hasher`], but no space is reserved up front.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{DefaultHasher, ShardedLruCache};
+    ///
+    /// let s = DefaultHasher::default();
+    /// let cache: ShardedLruCache<isize, Vec<u8>> =
+    ///     ShardedLruCache::weighted_with_hasher(64 << 20, 8, s);
+    /// assert_eq!(cache.cap(), 64 << 20);
+    /// ```
+    ///
+    /// [`with_hasher`]: #method.with_hasher
+    pub fn weighted_with_hasher(cap: usize, num_shards: usize, hash_builder: S) -> Self {
+        ShardedLruCache::split(
+            cap,
+            num_shards,
+            hash_builder,
+            0,
+            LruCache::weighted_with_hasher,
+        )
+    }
+
+    /// Creates a new sharded LRU Cache that never automatically evicts items and uses the
+    /// provided hash builder to hash keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{DefaultHasher, ShardedLruCache};
+    ///
+    /// let s = DefaultHasher::default();
+    /// let cache: ShardedLruCache<isize, &str> = ShardedLruCache::unbounded_with_hasher(4, s);
+    /// ```
+    pub fn unbounded_with_hasher(num_shards: usize, hash_builder: S) -> Self {
+        assert!(num_shards > 0, "a sharded cache needs at least one shard");
+        let shards = (0..num_shards)
+            .map(|_| Mutex::new(LruCache::unbounded_with_hasher(hash_builder.clone())))
+            .collect();
+        ShardedLruCache::construct(shards, hash_builder, 0)
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone, E: Epoch> ShardedLruCache<K, V, S, E> {
+    // Builds the shards with `make`, giving each of them an equal share of `cap`.
+    fn split<F>(cap: usize, num_shards: usize, hash_builder: S, epoch: E, make: F) -> Self
+    where
+        F: Fn(usize, S) -> LruCache<K, V, S, E>,
+    {
+        assert!(num_shards > 0, "a sharded cache needs at least one shard");
+        let num_shards = num_shards.min(cap).max(1);
+        let shards = (0..num_shards)
+            .map(|i| {
+                // hand out the remainder one by one so that the shares add up to `cap`
+                let share = cap / num_shards + usize::from(i < cap % num_shards);
+                Mutex::new(make(share, hash_builder.clone()))
+            })
+            .collect();
+        ShardedLruCache::construct(shards, hash_builder, epoch)
+    }
+
+    fn construct(shards: Shards<K, V, S, E>, hash_builder: S, epoch: E) -> Self {
+        ShardedLruCache {
+            shards,
+            hash_builder,
+            cur_epoch: Mutex::new(epoch),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, E: Epoch> ShardedLruCache<K, V, S, E> {
+    /// Puts a key-value pair into the shard that owns the key. If the key already exists in the
+    /// cache, then it updates the key's value and returns the old value. Otherwise, `None` is
+    /// returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::ShardedLruCache;
+    /// let cache = ShardedLruCache::new(2, 1);
+    ///
+    /// assert_eq!(None, cache.put(1, "a"));
+    /// assert_eq!(Some("a"), cache.put(1, "alpha"));
+    /// ```
+    pub fn put(&self, k: K, v: V) -> Option<V> {
+        self.shard(&k).put(k, v)
+    }
+
+    /// Puts a key-value pair with the given charge into the shard that owns the key. See
+    /// [`LruCache::put_with_charge`] for details.
+    ///
+    /// [`LruCache::put_with_charge`]: struct.LruCache.html#method.put_with_charge
+    pub fn put_with_charge(&self, k: K, v: V, charge: usize) -> Option<V> {
+        self.shard(&k).put_with_charge(k, v, charge)
+    }
+
+    /// Puts a key-value pair at the tail of its shard's LRU list, so that it is the shard's next
+    /// victim unless it is read first. See [`LruCache::put_cold`] for details.
+    ///
+    /// [`LruCache::put_cold`]: struct.LruCache.html#method.put_cold
+    pub fn put_cold(&self, k: K, v: V) -> Option<V> {
+        self.shard(&k).put_cold(k, v)
+    }
+
+    /// Pushes a key-value pair into the shard that owns the key. See [`LruCache::push`] for
+    /// details on the returned entry.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::ShardedLruCache;
+    /// let cache = ShardedLruCache::new(1, 1);
+    ///
+    /// assert_eq!(None, cache.push(1, "a"));
+    /// assert_eq!(Some((1, "a")), cache.push(2, "b"));
+    /// ```
+    ///
+    /// [`LruCache::push`]: struct.LruCache.html#method.push
+    pub fn push(&self, k: K, v: V) -> Option<(K, V)> {
+        self.shard(&k).push(k, v)
+    }
+
+    /// Pushes a key-value pair at the tail of its shard's LRU list. See [`LruCache::push_cold`]
+    /// for details on the returned entry.
+    ///
+    /// [`LruCache::push_cold`]: struct.LruCache.html#method.push_cold
+    pub fn push_cold(&self, k: K, v: V) -> Option<(K, V)> {
+        self.shard(&k).push_cold(k, v)
+    }
+
+    /// Returns a clone of the value of the key in the cache or `None` if it is not present in
+    /// the cache. Moves the key to the head of its shard's LRU list if it exists.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::ShardedLruCache;
+    /// let cache = ShardedLruCache::new(2, 2);
+    ///
+    /// cache.put(1, "a");
+    /// assert_eq!(cache.get(&1), Some("a"));
+    /// assert_eq!(cache.get(&2), None);
+    /// ```
+    pub fn get<Q>(&self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.shard(k).get(k).cloned()
+    }
+
+    /// Returns a clone of the value of the key in the cache or `None` if it is not present in
+    /// the cache. Unlike `get`, `peek` does not update the LRU list.
+    pub fn peek<Q>(&self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.shard(k).peek(k).cloned()
+    }
+
+    /// Returns a bool indicating whether the given key is in the cache. Does not update the
+    /// LRU list.
+    pub fn contains<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(k).contains(k)
+    }
+
+    /// Pins the entry of the given key in its shard and returns whether the key is in the cache.
+    /// See [`LruCache::pin`] for details.
+    ///
+    /// [`LruCache::pin`]: struct.LruCache.html#method.pin
+    pub fn pin<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(k).pin(k)
+    }
+
+    /// Releases a pin taken with `pin` and returns whether the key was pinned.
+    pub fn unpin<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(k).unpin(k)
+    }
+
+    /// Returns whether the entry of the given key is pinned.
+    pub fn is_pinned<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(k).is_pinned(k)
+    }
+
+    /// Removes and returns the value corresponding to the key from the cache or `None` if it
+    /// does not exist.
+    pub fn pop<Q>(&self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(k).pop(k)
+    }
+
+    /// Moves the entry of the given key to the head of its shard's LRU list without returning
+    /// its value, and returns whether the key is in the cache. See [`LruCache::promote`].
+    ///
+    /// [`LruCache::promote`]: struct.LruCache.html#method.promote
+    pub fn promote<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(k).promote(k)
+    }
+
+    /// Moves the entry of the given key to the tail of its shard's LRU list, so that it is the
+    /// shard's next victim, and returns whether the key is in the cache. See
+    /// [`LruCache::demote`].
+    ///
+    /// [`LruCache::demote`]: struct.LruCache.html#method.demote
+    pub fn demote<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(k).demote(k)
+    }
+
+    /// Update the current epoch of every shard. The given epoch should be greater than the
+    /// current epoch.
+    pub fn update_epoch(&self, epoch: E) {
+        let mut cur_epoch = self.cur_epoch.lock().unwrap();
+        assert!(epoch > *cur_epoch);
+        *cur_epoch = epoch;
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().update_epoch(epoch);
+        }
+    }
+
+    /// Returns the current epoch, which every shard follows.
+    pub fn current_epoch(&self) -> E {
+        *self.cur_epoch.lock().unwrap()
+    }
+
+    /// Evict entries by given epoch in every shard. Shards are locked one at a time.
+    pub fn evict_by_epoch(&self, epoch: E) {
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().evict_by_epoch(epoch);
+        }
+    }
+
+    /// Enables or disables the epoch index of every shard. See [`LruCache::set_epoch_index`]
+    /// for details.
+    ///
+    /// [`LruCache::set_epoch_index`]: struct.LruCache.html#method.set_epoch_index
+    pub fn set_epoch_index(&self, enabled: bool) {
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().set_epoch_index(enabled);
+        }
+    }
+
+    /// Returns the number of entries and their total charge for every epoch, summed over all
+    /// shards, from the oldest epoch to the newest one. Shards are locked one at a time. See
+    /// [`LruCache::epoch_histogram`] for details.
+    ///
+    /// [`LruCache::epoch_histogram`]: struct.LruCache.html#method.epoch_histogram
+    pub fn epoch_histogram(&self) -> Vec<(E, EpochUsage)> {
+        let mut histogram = BTreeMap::<E, EpochUsage>::new();
+        for shard in self.shards.iter() {
+            for (epoch, usage) in shard.lock().unwrap().epoch_histogram() {
+                let total = histogram.entry(epoch).or_default();
+                total.len += usage.len;
+                total.charge += usage.charge;
+            }
+        }
+        histogram.into_iter().collect()
+    }
+
+    /// Returns an estimate of the number of bytes allocated by all shards. Shards are locked
+    /// one at a time. See [`LruCache::memory_usage`] for details.
+    ///
+    /// [`LruCache::memory_usage`]: struct.LruCache.html#method.memory_usage
+    pub fn memory_usage(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().memory_usage())
+            .sum()
+    }
+
+    /// Returns `memory_usage` plus the memory the keys and values of all shards own on the
+    /// heap. Shards are locked one at a time.
+    pub fn deep_memory_usage(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().deep_memory_usage())
+            .sum()
+    }
+
+    /// Retains only the entries for which the predicate returns `true`. Shards are locked one
+    /// at a time, so entries put into a shard that was already visited are not seen. See
+    /// [`LruCache::retain`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::ShardedLruCache;
+    /// let cache = ShardedLruCache::new(8, 4);
+    ///
+    /// cache.put(("users", 1), "alice");
+    /// cache.put(("orders", 7), "pending");
+    /// cache.retain(|&(table, _), _| table != "users");
+    ///
+    /// assert_eq!(cache.len(), 1);
+    /// ```
+    ///
+    /// [`LruCache::retain`]: struct.LruCache.html#method.retain
+    pub fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().retain(&mut f);
+        }
+    }
+
+    /// Clears the contents of every shard.
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().clear();
+        }
+    }
+
+    /// Returns the number of key-value pairs that are currently in the cache, summed over all
+    /// shards. The result may be stale if other threads modify the cache concurrently.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap().len()).sum()
+    }
+
+    /// Returns a bool indicating whether every shard is empty.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.lock().unwrap().is_empty())
+    }
+
+    /// Returns the capacity of the cache, summed over all shards.
+    pub fn cap(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|s| s.lock().unwrap().cap())
+            .fold(0, usize::saturating_add)
+    }
+
+    /// Returns the number of pinned entries, summed over all shards.
+    pub fn pinned_len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|s| s.lock().unwrap().pinned_len())
+            .sum()
+    }
+
+    /// Returns whether some shard holds more than its share of the capacity because the
+    /// entries that could make room are pinned. See [`LruCache::is_overcommitted`].
+    ///
+    /// [`LruCache::is_overcommitted`]: struct.LruCache.html#method.is_overcommitted
+    pub fn is_overcommitted(&self) -> bool {
+        self.shards
+            .iter()
+            .any(|s| s.lock().unwrap().is_overcommitted())
+    }
+
+    /// Returns the total charge of the entries in the cache, summed over all shards.
+    pub fn usage(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap().usage()).sum()
+    }
+
+    /// Returns the counters of all shards added together.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> CacheStats {
+        self.shards
+            .iter()
+            .map(|s| s.lock().unwrap().stats())
+            .fold(CacheStats::default(), |acc, s| acc + s)
+    }
+
+    /// Resets the counters of every shard to zero.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().reset_stats();
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard<Q>(&self, k: &Q) -> MutexGuard<'_, LruCache<K, V, S, E>>
+    where
+        Q: Hash + ?Sized,
+    {
+        // hashbrown derives bucket positions from the low bits and tags from the top 7 bits, so
+        // pick the shard from the bits in between to keep them independent
+        let hash = self.hash_builder.hash_one(k);
+        let idx = ((hash >> 32) as usize) % self.shards.len();
+        self.shards[idx].lock().unwrap()
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, E: Epoch> fmt::Debug for ShardedLruCache<K, V, S, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShardedLruCache")
+            .field("num_shards", &self.num_shards())
+            .field("len", &self.len())
+            .field("cap", &self.cap())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShardedLruCache;
+    use scoped_threadpool::Pool;
+
+    #[test]
+    fn test_capacity_is_split_across_shards() {
+        let cache: ShardedLruCache<usize, ()> = ShardedLruCache::new(10, 4);
+        assert_eq!(cache.cap(), 10);
+
+        let shares: Vec<_> = cache
+            .shards
+            .iter()
+            .map(|s| s.lock().unwrap().cap())
+            .collect();
+        assert_eq!(shares, vec![3, 3, 2, 2]);
+
+        let cache: ShardedLruCache<usize, ()> = ShardedLruCache::new(2, 8);
+        assert_eq!(cache.num_shards(), 2);
+        assert!(cache.shards.iter().all(|s| s.lock().unwrap().cap() == 1));
+    }
+
+    #[test]
+    fn test_weighted_shards_reserve_nothing() {
+        let cache: ShardedLruCache<usize, ()> = ShardedLruCache::weighted(64 << 20, 4);
+        assert_eq!(cache.cap(), 64 << 20);
+        assert!(cache.memory_usage() < 4096);
+
+        cache.put_with_charge(1, (), 1 << 20);
+        assert_eq!(cache.usage(), 1 << 20);
+    }
+
+    #[test]
+    fn test_put_get_pop() {
+        let cache = ShardedLruCache::new(64, 4);
+        for i in 0..32 {
+            assert_eq!(cache.put(i, i * 2), None);
+        }
+        assert_eq!(cache.len(), 32);
+
+        for i in 0..32 {
+            assert_eq!(cache.get(&i), Some(i * 2));
+        }
+        assert_eq!(cache.pop(&7), Some(14));
+        assert!(!cache.contains(&7));
+        assert_eq!(cache.len(), 31);
+    }
+
+    #[test]
+    fn test_epoch_is_shared_by_shards() {
+        let cache = ShardedLruCache::unbounded(4);
+        for i in 0..16 {
+            cache.put(i, ());
+        }
+        cache.update_epoch(1);
+        for i in 16..32 {
+            cache.put(i, ());
+        }
+        assert_eq!(cache.current_epoch(), 1);
+
+        cache.evict_by_epoch(1);
+        assert_eq!(cache.len(), 16);
+        assert!((0..16).all(|i| !cache.contains(&i)));
+        assert!((16..32).all(|i| cache.contains(&i)));
+    }
+
+    #[test]
+    fn test_custom_epoch_type() {
+        let cache = ShardedLruCache::with_epoch(8, 2, (0u32, 0u32));
+        cache.put(1, ());
+        cache.update_epoch((0, 1));
+        cache.put(2, ());
+        cache.update_epoch((1, 0));
+        cache.put(3, ());
+
+        cache.evict_by_epoch((1, 0));
+        assert_eq!(cache.current_epoch(), (1, 0));
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains(&3));
+        assert_eq!(cache.epoch_histogram().len(), 1);
+    }
+
+    #[test]
+    fn test_pinned_entries_are_reported() {
+        let cache = ShardedLruCache::new(1, 1);
+        cache.put(1, "a");
+        assert!(cache.pin(&1));
+        assert!(cache.is_pinned(&1));
+        assert!(!cache.is_pinned(&2));
+
+        cache.put(2, "b");
+        assert_eq!(cache.pinned_len(), 1);
+        assert!(cache.is_overcommitted());
+
+        cache.unpin(&1);
+        assert_eq!(cache.pinned_len(), 0);
+        assert!(!cache.is_overcommitted());
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_multiple_threads() {
+        let mut pool = Pool::new(4);
+        let cache = ShardedLruCache::new(1024, 8);
+
+        let cache_ref = &cache;
+        pool.scoped(|scoped| {
+            for t in 0..4 {
+                scoped.execute(move || {
+                    for i in 0..100 {
+                        cache_ref.put(t * 100 + i, i);
+                        assert_eq!(cache_ref.get(&(t * 100 + i)), Some(i));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(cache.len(), 400);
+    }
+}
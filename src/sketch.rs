@@ -0,0 +1,156 @@
+// MIT License
+
+// Copyright (c) 2016 Jerome Froelich
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The count-min sketch that estimates how often keys are accessed for the admission filter of
+//! an `LruCache`.
+
+use std::mem;
+
+// The table is never grown past this many words, whatever the capacity of the cache.
+const MAX_TABLE_LEN: usize = 1 << 20;
+
+// Multipliers that derive one index per row of the sketch from a single key hash.
+const SEEDS: [u64; 4] = [
+    0xc3a5_c85c_97cb_3127,
+    0xb492_b66f_be98_f273,
+    0x9ae1_6a3b_2f90_404f,
+    0xcbf2_9ce4_8422_2325,
+];
+
+// Approximates the access frequency of keys in a table of 4-bit counters, sixteen to a word.
+// Each key is counted in four rows and its frequency is the minimum of those counters. Every
+// counter is halved once the number of increments reaches the sample size, so the sketch
+// favors recent popularity over old one.
+#[derive(Clone)]
+pub(crate) struct FrequencySketch {
+    table: Box<[u64]>,
+    additions: usize,
+    sample_size: usize,
+}
+
+impl FrequencySketch {
+    // Creates a sketch sized for a cache that holds about `cap` entries.
+    pub(crate) fn with_capacity(cap: usize) -> FrequencySketch {
+        let len = cap.clamp(1, MAX_TABLE_LEN).next_power_of_two();
+        FrequencySketch {
+            table: vec![0; len].into_boxed_slice(),
+            additions: 0,
+            sample_size: len * 10,
+        }
+    }
+
+    // Records one access of the key with the given hash.
+    pub(crate) fn increment(&mut self, hash: u64) {
+        let mut added = false;
+        for row in 0..SEEDS.len() {
+            let (slot, shift) = self.index(hash, row);
+            if (self.table[slot] >> shift) & 0xf < 0xf {
+                self.table[slot] += 1 << shift;
+                added = true;
+            }
+        }
+
+        if added {
+            self.additions += 1;
+            if self.additions >= self.sample_size {
+                self.age();
+            }
+        }
+    }
+
+    // Returns the estimated number of recent accesses of the key with the given hash, which is
+    // at most 15.
+    pub(crate) fn frequency(&self, hash: u64) -> u8 {
+        (0..SEEDS.len())
+            .map(|row| {
+                let (slot, shift) = self.index(hash, row);
+                ((self.table[slot] >> shift) & 0xf) as u8
+            })
+            .min()
+            .unwrap()
+    }
+
+    // Returns the number of bytes taken by the table of counters.
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.table.len() * mem::size_of::<u64>()
+    }
+
+    // Returns the word and the bit offset within it of the key's counter in the given row.
+    fn index(&self, hash: u64, row: usize) -> (usize, u32) {
+        let mixed = (hash ^ (hash >> 29)).wrapping_mul(SEEDS[row]);
+        let mixed = mixed ^ (mixed >> 32);
+        let slot = mixed as usize & (self.table.len() - 1);
+        let shift = ((mixed >> 60) as u32) * 4;
+        (slot, shift)
+    }
+
+    // Halves every counter, clearing the bit each one takes from its upper neighbour.
+    fn age(&mut self) {
+        for word in self.table.iter_mut() {
+            *word = (*word >> 1) & 0x7777_7777_7777_7777;
+        }
+        self.additions /= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrequencySketch;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash<T: Hash>(t: T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_frequency_saturates() {
+        let mut sketch = FrequencySketch::with_capacity(64);
+        assert_eq!(sketch.frequency(hash(1)), 0);
+
+        for expected in 1..=15 {
+            sketch.increment(hash(1));
+            assert_eq!(sketch.frequency(hash(1)), expected);
+        }
+        sketch.increment(hash(1));
+        assert_eq!(sketch.frequency(hash(1)), 15);
+        assert_eq!(sketch.frequency(hash(2)), 0);
+    }
+
+    #[test]
+    fn test_aging_halves_counters() {
+        let mut sketch = FrequencySketch::with_capacity(16);
+        for _ in 0..8 {
+            sketch.increment(hash("hot"));
+        }
+
+        // enough distinct keys to reach the sample size of 160 increments
+        for i in 0..160 {
+            sketch.increment(hash(i));
+        }
+
+        assert!(sketch.frequency(hash("hot")) < 8);
+        assert!(sketch.additions < sketch.sample_size);
+    }
+}
@@ -0,0 +1,1359 @@
+// MIT License
+
+// Copyright (c) 2016 Jerome Froelich
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! An LRU cache that keeps its entries in a contiguous slab instead of allocating each one on
+//! its own.
+
+use alloc::borrow::Borrow;
+#[cfg(feature = "allocator-api")]
+use allocator_api2::alloc::{Allocator, Global};
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem;
+
+use hashbrown::hash_map::RawEntryMut;
+use hashbrown::HashMap;
+
+use crate::heap_size::{self, HeapSize};
+use crate::{DefaultHasher, Epoch};
+
+// The index that stands for no slot at the ends of the list.
+const NIL: u32 = u32::MAX;
+
+// A slot of the slab. Occupied slots are linked into the LRU list through `prev` and `next`,
+// free slots into the free list through `next`.
+struct Slot<K, V, E> {
+    entry: Option<(K, V)>,
+    epoch: E,
+    prev: u32,
+    next: u32,
+}
+
+#[cfg(feature = "allocator-api")]
+type Slots<K, V, E, A> = allocator_api2::vec::Vec<Slot<K, V, E>, A>;
+
+#[cfg(not(feature = "allocator-api"))]
+type Slots<K, V, E> = Vec<Slot<K, V, E>>;
+
+/// An LRU Cache that stores its entries in a contiguous slab.
+///
+/// The entries are linked by their index in the slab rather than by pointer, and the hash table
+/// holds indices only, so an entry costs no allocation of its own and neighbouring entries
+/// share cache lines. The slots of removed entries are reused by later insertions, and the
+/// slab never shrinks. A `SlabLruCache` holds fewer than `u32::MAX` entries.
+///
+/// It has the API that [`LruCache`] had before any of the features it gained since: the same
+/// constructors, including the `_in` ones with the `allocator-api` feature, the same
+/// operations, epoch based eviction and iterators, all under the same names. Of the later
+/// additions it only has `unbounded_with_epoch`, `epoch_of`, `memory_usage` and
+/// `deep_memory_usage`. It does not have charges, time-to-live, segments, the admission
+/// window, pinning, eviction listeners, the epoch index, cursors, the entry API, fallible
+/// insertion, statistics, serialization or cloning.
+///
+/// # Example
+///
+/// ```
+/// use lru::SlabLruCache;
+///
+/// let mut cache = SlabLruCache::new(2);
+/// cache.put("apple", 3);
+/// cache.put("banana", 2);
+/// cache.put("pear", 4);
+///
+/// assert_eq!(cache.get(&"apple"), None);
+/// assert_eq!(cache.get(&"banana"), Some(&2));
+/// assert_eq!(cache.get(&"pear"), Some(&4));
+/// ```
+///
+/// [`LruCache`]: struct.LruCache.html
+pub struct SlabLruCache<
+    K,
+    V,
+    S = DefaultHasher,
+    E = u64,
+    #[cfg(feature = "allocator-api")] A: Clone + Allocator = Global,
+> {
+    slots: with_alloc!(Slots<K, V, E>),
+    // maps the hash of every key to the index of its slot; the slots are hashed with
+    // `hash_builder` by the cache itself, as the table cannot reach them
+    #[cfg(feature = "allocator-api")]
+    map: HashMap<u32, (), (), A>,
+    #[cfg(not(feature = "allocator-api"))]
+    map: HashMap<u32, (), ()>,
+    hash_builder: S,
+    cap: usize,
+
+    // the most and the least recently used entries
+    head: u32,
+    tail: u32,
+    // the first free slot
+    free: u32,
+
+    cur_epoch: E,
+}
+
+impl<K: Hash + Eq, V> SlabLruCache<K, V> {
+    /// Creates a new slab LRU Cache that holds at most `cap` items. Room for `cap` items is
+    /// reserved up front.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache: SlabLruCache<isize, &str> = SlabLruCache::new(10);
+    /// ```
+    pub fn new(cap: usize) -> SlabLruCache<K, V> {
+        SlabLruCache::with_hasher(cap, DefaultHasher::default())
+    }
+
+    /// Creates a new slab LRU Cache that never automatically evicts items.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache: SlabLruCache<isize, &str> = SlabLruCache::unbounded();
+    /// ```
+    pub fn unbounded() -> SlabLruCache<K, V> {
+        SlabLruCache::unbounded_with_hasher(DefaultHasher::default())
+    }
+}
+
+impl<K: Hash + Eq, V, E: Epoch> SlabLruCache<K, V, DefaultHasher, E> {
+    /// Creates a new slab LRU Cache that holds at most `cap` items and starts in the given
+    /// epoch, which may be of any [`Epoch`] type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let cache: SlabLruCache<isize, &str, _, u32> = SlabLruCache::with_epoch(10, 7);
+    /// assert_eq!(cache.current_epoch(), 7);
+    /// ```
+    ///
+    /// [`Epoch`]: trait.Epoch.html
+    pub fn with_epoch(cap: usize, epoch: E) -> SlabLruCache<K, V, DefaultHasher, E> {
+        SlabLruCache::construct(
+            cap,
+            Slots::with_capacity(cap),
+            DefaultHasher::default(),
+            epoch,
+        )
+    }
+
+    /// Creates a new slab LRU Cache that never automatically evicts items and starts in the
+    /// given epoch.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let cache: SlabLruCache<isize, &str, _, u32> = SlabLruCache::unbounded_with_epoch(7);
+    /// assert_eq!(cache.current_epoch(), 7);
+    /// ```
+    pub fn unbounded_with_epoch(epoch: E) -> SlabLruCache<K, V, DefaultHasher, E> {
+        SlabLruCache::construct(usize::MAX, Slots::new(), DefaultHasher::default(), epoch)
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> SlabLruCache<K, V, S> {
+    /// Creates a new slab LRU Cache that holds at most `cap` items and uses the provided hash
+    /// builder to hash keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{DefaultHasher, SlabLruCache};
+    ///
+    /// let s = DefaultHasher::default();
+    /// let mut cache: SlabLruCache<isize, &str> = SlabLruCache::with_hasher(10, s);
+    /// ```
+    pub fn with_hasher(cap: usize, hash_builder: S) -> SlabLruCache<K, V, S> {
+        SlabLruCache::construct(cap, Slots::with_capacity(cap), hash_builder, 0)
+    }
+
+    /// Creates a new slab LRU Cache that never automatically evicts items and uses the provided
+    /// hash builder to hash keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::{DefaultHasher, SlabLruCache};
+    ///
+    /// let s = DefaultHasher::default();
+    /// let mut cache: SlabLruCache<isize, &str> = SlabLruCache::unbounded_with_hasher(s);
+    /// ```
+    pub fn unbounded_with_hasher(hash_builder: S) -> SlabLruCache<K, V, S> {
+        SlabLruCache::construct(usize::MAX, Slots::new(), hash_builder, 0)
+    }
+}
+
+#[cfg(feature = "allocator-api")]
+impl<K: Hash + Eq, V, A: Clone + Allocator> SlabLruCache<K, V, DefaultHasher, u64, A> {
+    /// Creates a new slab LRU Cache that holds at most `cap` items and keeps its slab and its
+    /// hash table in the given allocator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// # extern crate allocator_api2;
+    /// # extern crate lru;
+    /// use allocator_api2::alloc::Global;
+    /// use lru::SlabLruCache;
+    ///
+    /// # fn main() {
+    /// let mut cache: SlabLruCache<isize, &str, _, u64, Global> = SlabLruCache::new_in(10, Global);
+    /// cache.put(1, "a");
+    /// assert_eq!(cache.get(&1), Some(&"a"));
+    /// # }
+    /// ```
+    pub fn new_in(cap: usize, alloc: A) -> Self {
+        SlabLruCache::with_hasher_in(cap, DefaultHasher::default(), alloc)
+    }
+
+    /// Creates a new slab LRU Cache that never automatically evicts items and keeps its slab
+    /// and its hash table in the given allocator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// # extern crate allocator_api2;
+    /// # extern crate lru;
+    /// use allocator_api2::alloc::Global;
+    /// use lru::SlabLruCache;
+    ///
+    /// # fn main() {
+    /// let mut cache: SlabLruCache<isize, &str, _, u64, Global> =
+    ///     SlabLruCache::unbounded_in(Global);
+    /// # }
+    /// ```
+    pub fn unbounded_in(alloc: A) -> Self {
+        SlabLruCache::unbounded_with_hasher_in(DefaultHasher::default(), alloc)
+    }
+}
+
+#[cfg(feature = "allocator-api")]
+impl<K: Hash + Eq, V, S: BuildHasher, A: Clone + Allocator> SlabLruCache<K, V, S, u64, A> {
+    /// Creates a new slab LRU Cache that holds at most `cap` items, uses the provided hash
+    /// builder to hash keys and keeps its slab and its hash table in the given allocator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// # extern crate allocator_api2;
+    /// # extern crate lru;
+    /// use allocator_api2::alloc::Global;
+    /// use lru::{DefaultHasher, SlabLruCache};
+    ///
+    /// # fn main() {
+    /// let s = DefaultHasher::default();
+    /// let mut cache: SlabLruCache<isize, &str, _, u64, Global> =
+    ///     SlabLruCache::with_hasher_in(10, s, Global);
+    /// # }
+    /// ```
+    pub fn with_hasher_in(cap: usize, hash_builder: S, alloc: A) -> Self {
+        SlabLruCache::construct(cap, Slots::with_capacity_in(cap, alloc), hash_builder, 0)
+    }
+
+    /// Creates a new slab LRU Cache that never automatically evicts items, uses the provided
+    /// hash builder to hash keys and keeps its slab and its hash table in the given allocator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #![cfg_attr(feature = "nightly", feature(allocator_api))]
+    /// # extern crate allocator_api2;
+    /// # extern crate lru;
+    /// use allocator_api2::alloc::Global;
+    /// use lru::{DefaultHasher, SlabLruCache};
+    ///
+    /// # fn main() {
+    /// let s = DefaultHasher::default();
+    /// let mut cache: SlabLruCache<isize, &str, _, u64, Global> =
+    ///     SlabLruCache::unbounded_with_hasher_in(s, Global);
+    /// # }
+    /// ```
+    pub fn unbounded_with_hasher_in(hash_builder: S, alloc: A) -> Self {
+        SlabLruCache::construct(usize::MAX, Slots::new_in(alloc), hash_builder, 0)
+    }
+}
+
+impl<
+        K: Hash + Eq,
+        V,
+        S: BuildHasher,
+        E: Epoch,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator,
+    > with_alloc!(SlabLruCache<K, V, S, E>)
+{
+    // Creates a cache around the given slab, with a hash table in the same allocator that has
+    // room for as many entries as the slab.
+    fn construct(
+        cap: usize,
+        slots: with_alloc!(Slots<K, V, E>),
+        hash_builder: S,
+        epoch: E,
+    ) -> Self {
+        #[cfg(feature = "allocator-api")]
+        let map =
+            HashMap::with_capacity_and_hasher_in(slots.capacity(), (), slots.allocator().clone());
+        #[cfg(not(feature = "allocator-api"))]
+        let map = HashMap::with_capacity_and_hasher(slots.capacity(), ());
+        SlabLruCache {
+            slots,
+            map,
+            hash_builder,
+            cap,
+            head: NIL,
+            tail: NIL,
+            free: NIL,
+            cur_epoch: epoch,
+        }
+    }
+
+    /// Puts a key-value pair into cache. If the key already exists in the cache, then it updates
+    /// the key's value and returns the old value. Otherwise, `None` is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(2);
+    ///
+    /// assert_eq!(None, cache.put(1, "a"));
+    /// assert_eq!(None, cache.put(2, "b"));
+    /// assert_eq!(Some("b"), cache.put(2, "beta"));
+    ///
+    /// assert_eq!(cache.get(&1), Some(&"a"));
+    /// assert_eq!(cache.get(&2), Some(&"beta"));
+    /// ```
+    pub fn put(&mut self, k: K, v: V) -> Option<V> {
+        match self.capturing_put(k, v) {
+            Some((true, _, v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Pushes a key-value pair into the cache. If an entry with key `k` already exists in
+    /// the cache or another cache entry is removed (due to the lru's capacity),
+    /// then it returns the old entry's key-value pair. Otherwise, returns `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(2);
+    ///
+    /// assert_eq!(None, cache.push(1, "a"));
+    /// assert_eq!(None, cache.push(2, "b"));
+    /// assert_eq!(Some((2, "b")), cache.push(2, "beta"));
+    /// assert_eq!(Some((1, "a")), cache.push(3, "alpha"));
+    /// ```
+    pub fn push(&mut self, k: K, v: V) -> Option<(K, V)> {
+        self.capturing_put(k, v).map(|(_, k, v)| (k, v))
+    }
+
+    // Used internally by `put` and `push`. Returns the entry that was replaced, flagged with
+    // whether it had the same key.
+    fn capturing_put(&mut self, k: K, v: V) -> Option<(bool, K, V)> {
+        let hash = self.hash_builder.hash_one(&k);
+        if let Some(idx) = self.find(hash, &k) {
+            self.touch(idx);
+            let (_, val) = self.slot_mut(idx).entry.as_mut().unwrap();
+            return Some((true, k, mem::replace(val, v)));
+        }
+
+        if self.cap == 0 {
+            return None;
+        }
+        let replaced = if self.len() >= self.cap {
+            self.pop_lru()
+        } else {
+            None
+        };
+        self.insert(hash, k, v);
+        replaced.map(|(k, v)| (false, k, v))
+    }
+
+    /// Returns a reference to the value of the key in the cache or `None` if it is not
+    /// present in the cache. Moves the key to the head of the LRU list if it exists.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.put(2, "c");
+    /// cache.put(3, "d");
+    ///
+    /// assert_eq!(cache.get(&1), None);
+    /// assert_eq!(cache.get(&2), Some(&"c"));
+    /// assert_eq!(cache.get(&3), Some(&"d"));
+    /// ```
+    pub fn get<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let idx = self.find(self.hash_builder.hash_one(k), k)?;
+        self.touch(idx);
+        self.slot(idx).entry.as_ref().map(|(_, v)| v)
+    }
+
+    /// Returns a mutable reference to the value of the key in the cache or `None` if it
+    /// is not present in the cache. Moves the key to the head of the LRU list if it exists.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(2);
+    ///
+    /// cache.put("apple", 8);
+    /// cache.put("banana", 4);
+    ///
+    /// if let Some(v) = cache.get_mut(&"apple") {
+    ///     *v = 6;
+    /// }
+    /// assert_eq!(cache.get(&"apple"), Some(&6));
+    /// ```
+    pub fn get_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let idx = self.find(self.hash_builder.hash_one(k), k)?;
+        self.touch(idx);
+        self.slot_mut(idx).entry.as_mut().map(|(_, v)| v)
+    }
+
+    /// Returns a reference to the value of the key in the cache if it is present in the cache
+    /// and moves the key to the head of the LRU list. If the key does not exist the provided
+    /// `Fn` is used to populate the list and a reference is returned.
+    ///
+    /// This method will only return `None` when the capacity of the cache is 0 and no entries
+    /// can be populated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(2);
+    ///
+    /// assert_eq!(cache.get_or_insert(1, || "a"), Some(&"a"));
+    /// assert_eq!(cache.get_or_insert(1, || "b"), Some(&"a"));
+    /// ```
+    pub fn get_or_insert<F>(&mut self, k: K, f: F) -> Option<&V>
+    where
+        F: Fn() -> V,
+    {
+        let hash = self.hash_builder.hash_one(&k);
+        let idx = match self.find(hash, &k) {
+            Some(idx) => {
+                self.touch(idx);
+                idx
+            }
+            None => {
+                if self.cap == 0 {
+                    return None;
+                }
+                if self.len() >= self.cap {
+                    self.pop_lru();
+                }
+                self.insert(hash, k, f())
+            }
+        };
+        self.slot(idx).entry.as_ref().map(|(_, v)| v)
+    }
+
+    /// Returns a reference to the value corresponding to the key in the cache or `None` if it is
+    /// not present in the cache. Unlike `get`, `peek` does not update the LRU list so the key's
+    /// position will be unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    ///
+    /// assert_eq!(cache.peek(&1), Some(&"a"));
+    /// assert_eq!(cache.peek(&2), Some(&"b"));
+    /// ```
+    pub fn peek<'a, Q>(&'a self, k: &Q) -> Option<&'a V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let idx = self.find(self.hash_builder.hash_one(k), k)?;
+        self.slot(idx).entry.as_ref().map(|(_, v)| v)
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key in the cache or `None`
+    /// if it is not present in the cache. Unlike `get_mut`, `peek_mut` does not update the LRU
+    /// list so the key's position will be unchanged.
+    pub fn peek_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let idx = self.find(self.hash_builder.hash_one(k), k)?;
+        self.slot_mut(idx).entry.as_mut().map(|(_, v)| v)
+    }
+
+    /// Returns the key and value of the least recently used entry or `None` if the cache is
+    /// empty. Does not update the LRU list.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(2);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    ///
+    /// assert_eq!(cache.peek_lru(), Some((&1, &"a")));
+    /// ```
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        if self.tail == NIL {
+            return None;
+        }
+        self.slot(self.tail).entry.as_ref().map(|(k, v)| (k, v))
+    }
+
+    /// Returns the epoch of the entry of the given key, or `None` if it is not in the cache.
+    /// Does not update the LRU list.
+    pub fn epoch_of<Q>(&self, k: &Q) -> Option<E>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let idx = self.find(self.hash_builder.hash_one(k), k)?;
+        Some(self.slot(idx).epoch)
+    }
+
+    /// Returns a bool indicating whether the given key is in the cache. Does not update the
+    /// LRU list.
+    pub fn contains<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(self.hash_builder.hash_one(k), k).is_some()
+    }
+
+    /// Removes and returns the value corresponding to the key from the cache or `None` if it
+    /// does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(2);
+    ///
+    /// cache.put(2, "a");
+    ///
+    /// assert_eq!(cache.pop(&1), None);
+    /// assert_eq!(cache.pop(&2), Some("a"));
+    /// assert_eq!(cache.pop(&2), None);
+    /// assert_eq!(cache.len(), 0);
+    /// ```
+    pub fn pop<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.pop_entry(k).map(|(_, v)| v)
+    }
+
+    /// Removes and returns the key and the value corresponding to the key from the cache or
+    /// `None` if it does not exist.
+    pub fn pop_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash_builder.hash_one(k);
+        let idx = self.find(hash, k)?;
+        Some(self.remove(hash, idx))
+    }
+
+    /// Removes and returns the key and value corresponding to the least recently
+    /// used item or `None` if the cache is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(2);
+    ///
+    /// cache.put(2, "a");
+    /// cache.put(3, "b");
+    /// cache.get(&2);
+    ///
+    /// assert_eq!(cache.pop_lru(), Some((3, "b")));
+    /// assert_eq!(cache.pop_lru(), Some((2, "a")));
+    /// assert_eq!(cache.pop_lru(), None);
+    /// ```
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        if self.tail == NIL {
+            return None;
+        }
+        let idx = self.tail;
+        let hash = self.hash_slot(idx);
+        Some(self.remove(hash, idx))
+    }
+
+    /// Returns the number of key-value pairs that are currently in the the cache.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns a bool indicating whether the cache is empty or not.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the maximum number of key-value pairs the cache can hold.
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    /// Returns an estimate of the number of bytes the cache has allocated for its slab and its
+    /// hash table. Keys and values only count with their inline size; see
+    /// [`deep_memory_usage`] for what they own on the heap.
+    ///
+    /// [`deep_memory_usage`]: #method.deep_memory_usage
+    pub fn memory_usage(&self) -> usize {
+        self.slots.capacity() * mem::size_of::<Slot<K, V, E>>()
+            + heap_size::table_size::<u32>(self.map.capacity())
+    }
+
+    /// Returns [`memory_usage`] plus the memory the keys and values own on the heap, as
+    /// reported by their [`HeapSize`] implementations. Takes time linear in the number of
+    /// entries.
+    ///
+    /// [`memory_usage`]: #method.memory_usage
+    /// [`HeapSize`]: trait.HeapSize.html
+    pub fn deep_memory_usage(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let owned: usize = self
+            .iter()
+            .map(|(k, v)| k.heap_size() + v.heap_size())
+            .sum();
+        self.memory_usage() + owned
+    }
+
+    /// Resizes the cache. If the new capacity is smaller than the size of the current
+    /// cache any entries past the new capacity are discarded. The hash table is shrunk to fit,
+    /// but the slab keeps its size.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(3);
+    ///
+    /// cache.put(1, "a");
+    /// cache.put(2, "b");
+    /// cache.put(3, "c");
+    /// cache.resize(2);
+    ///
+    /// assert_eq!(cache.len(), 2);
+    /// assert!(!cache.contains(&1));
+    /// ```
+    pub fn resize(&mut self, cap: usize) {
+        while self.len() > cap {
+            self.pop_lru();
+        }
+        self.shrink_map();
+        self.cap = cap;
+    }
+
+    /// Update the current epoch. The given epoch should be greater than the current epoch.
+    pub fn update_epoch(&mut self, epoch: E) {
+        assert!(epoch > self.cur_epoch);
+        self.cur_epoch = epoch;
+    }
+
+    pub fn current_epoch(&self) -> E {
+        self.cur_epoch
+    }
+
+    /// Removes and returns the least recently used entry along with its epoch if its epoch is
+    /// older than the given one, or `None` otherwise.
+    pub fn pop_lru_by_epoch(&mut self, epoch: E) -> Option<(K, V, E)> {
+        if self.tail == NIL || self.slot(self.tail).epoch >= epoch {
+            return None;
+        }
+        let node_epoch = self.slot(self.tail).epoch;
+        self.pop_lru().map(|(k, v)| (k, v, node_epoch))
+    }
+
+    /// Evicts the least recently used entries as long as their epoch is older than the given
+    /// one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    /// let mut cache = SlabLruCache::new(3);
+    ///
+    /// cache.put(1, "a");
+    /// cache.update_epoch(1);
+    /// cache.put(2, "b");
+    /// cache.evict_by_epoch(1);
+    ///
+    /// assert!(!cache.contains(&1));
+    /// assert!(cache.contains(&2));
+    /// ```
+    pub fn evict_by_epoch(&mut self, epoch: E) {
+        while self.pop_lru_by_epoch(epoch).is_some() {}
+    }
+
+    /// Clears the contents of the cache and releases its slots.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.map.clear();
+        self.head = NIL;
+        self.tail = NIL;
+        self.free = NIL;
+    }
+
+    /// An iterator visiting all entries in most-recently used order. The iterator element type
+    /// is `(&K, &V)`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    ///
+    /// let mut cache = SlabLruCache::new(3);
+    /// cache.put("a", 1);
+    /// cache.put("b", 2);
+    /// cache.put("c", 3);
+    ///
+    /// let keys: Vec<_> = cache.iter().map(|(k, _)| *k).collect();
+    /// assert_eq!(keys, ["c", "b", "a"]);
+    /// ```
+    pub fn iter(&self) -> SlabIter<'_, K, V, E> {
+        SlabIter {
+            slots: &self.slots,
+            front: self.head,
+            back: self.tail,
+            len: self.len(),
+        }
+    }
+
+    /// An iterator visiting all entries in most-recently-used order, giving a mutable reference
+    /// on V. The iterator element type is `(&K, &mut V)`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lru::SlabLruCache;
+    ///
+    /// let mut cache = SlabLruCache::new(2);
+    /// cache.put("a", 1);
+    /// cache.put("b", 2);
+    ///
+    /// for (_, val) in cache.iter_mut() {
+    ///     *val *= 10;
+    /// }
+    /// assert_eq!(cache.get(&"a"), Some(&10));
+    /// ```
+    pub fn iter_mut(&mut self) -> SlabIterMut<'_, K, V, E> {
+        SlabIterMut {
+            slots: self.slots.as_mut_ptr(),
+            front: self.head,
+            back: self.tail,
+            len: self.len(),
+            phantom: PhantomData,
+        }
+    }
+
+    fn slot(&self, idx: u32) -> &Slot<K, V, E> {
+        &self.slots[idx as usize]
+    }
+
+    fn slot_mut(&mut self, idx: u32) -> &mut Slot<K, V, E> {
+        &mut self.slots[idx as usize]
+    }
+
+    fn hash_slot(&self, idx: u32) -> u64 {
+        let (k, _) = self.slot(idx).entry.as_ref().unwrap();
+        self.hash_builder.hash_one(k)
+    }
+
+    fn find<Q>(&self, hash: u64, k: &Q) -> Option<u32>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let slots = &self.slots;
+        self.map
+            .raw_entry()
+            .from_hash(hash, |&idx| match slots[idx as usize].entry {
+                Some((ref key, _)) => key.borrow() == k,
+                None => false,
+            })
+            .map(|(&idx, _)| idx)
+    }
+
+    // Stores a new entry in a free slot, or a new one at the end of the slab, at the head of
+    // the list.
+    fn insert(&mut self, hash: u64, k: K, v: V) -> u32 {
+        let slot = Slot {
+            entry: Some((k, v)),
+            epoch: self.cur_epoch,
+            prev: NIL,
+            next: NIL,
+        };
+        let idx = if self.free != NIL {
+            let idx = self.free;
+            self.free = self.slot(idx).next;
+            *self.slot_mut(idx) = slot;
+            idx
+        } else {
+            assert!(
+                self.slots.len() < NIL as usize,
+                "a SlabLruCache holds fewer than u32::MAX entries"
+            );
+            self.slots.push(slot);
+            (self.slots.len() - 1) as u32
+        };
+        self.attach(idx);
+
+        let (slots, hash_builder) = (&self.slots, &self.hash_builder);
+        match self.map.raw_entry_mut().from_hash(hash, |_| false) {
+            RawEntryMut::Vacant(entry) => {
+                entry.insert_with_hasher(hash, idx, (), |&i| {
+                    let (k, _) = slots[i as usize].entry.as_ref().unwrap();
+                    hash_builder.hash_one(k)
+                });
+            }
+            RawEntryMut::Occupied(_) => unreachable!(),
+        }
+        idx
+    }
+
+    // Replaces the hash table with one that is just large enough for the entries. The table has
+    // no hasher of its own to rehash with, so the entries are inserted into a new one.
+    fn shrink_map(&mut self) {
+        #[cfg(feature = "allocator-api")]
+        let mut map =
+            HashMap::with_capacity_and_hasher_in(self.len(), (), self.map.allocator().clone());
+        #[cfg(not(feature = "allocator-api"))]
+        let mut map = HashMap::with_capacity_and_hasher(self.len(), ());
+
+        let (slots, hash_builder) = (&self.slots, &self.hash_builder);
+        let mut idx = self.head;
+        while idx != NIL {
+            let (k, _) = slots[idx as usize].entry.as_ref().unwrap();
+            let hash = hash_builder.hash_one(k);
+            match map.raw_entry_mut().from_hash(hash, |_| false) {
+                RawEntryMut::Vacant(entry) => {
+                    entry.insert_with_hasher(hash, idx, (), |&i| {
+                        let (k, _) = slots[i as usize].entry.as_ref().unwrap();
+                        hash_builder.hash_one(k)
+                    });
+                }
+                RawEntryMut::Occupied(_) => unreachable!(),
+            }
+            idx = slots[idx as usize].next;
+        }
+        self.map = map;
+    }
+
+    // Takes the entry in the given slot out of the cache and puts the slot on the free list.
+    fn remove(&mut self, hash: u64, idx: u32) -> (K, V) {
+        match self.map.raw_entry_mut().from_hash(hash, |&i| i == idx) {
+            RawEntryMut::Occupied(entry) => {
+                entry.remove();
+            }
+            RawEntryMut::Vacant(_) => unreachable!(),
+        }
+        self.detach(idx);
+        let free = self.free;
+        self.free = idx;
+        let slot = self.slot_mut(idx);
+        slot.next = free;
+        slot.entry.take().unwrap()
+    }
+
+    // Moves an entry to the head of the list and gives it the current epoch.
+    fn touch(&mut self, idx: u32) {
+        self.slot_mut(idx).epoch = self.cur_epoch;
+        if self.head != idx {
+            self.detach(idx);
+            self.attach(idx);
+        }
+    }
+
+    fn detach(&mut self, idx: u32) {
+        let (prev, next) = (self.slot(idx).prev, self.slot(idx).next);
+        if prev == NIL {
+            self.head = next;
+        } else {
+            self.slot_mut(prev).next = next;
+        }
+        if next == NIL {
+            self.tail = prev;
+        } else {
+            self.slot_mut(next).prev = prev;
+        }
+    }
+
+    fn attach(&mut self, idx: u32) {
+        let head = self.head;
+        let slot = self.slot_mut(idx);
+        slot.prev = NIL;
+        slot.next = head;
+        if head == NIL {
+            self.tail = idx;
+        } else {
+            self.slot_mut(head).prev = idx;
+        }
+        self.head = idx;
+    }
+}
+
+impl<
+        'a,
+        K: Hash + Eq,
+        V,
+        S: BuildHasher,
+        E: Epoch,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator,
+    > IntoIterator for &'a with_alloc!(SlabLruCache<K, V, S, E>)
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = SlabIter<'a, K, V, E>;
+
+    fn into_iter(self) -> SlabIter<'a, K, V, E> {
+        self.iter()
+    }
+}
+
+impl<
+        'a,
+        K: Hash + Eq,
+        V,
+        S: BuildHasher,
+        E: Epoch,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator,
+    > IntoIterator for &'a mut with_alloc!(SlabLruCache<K, V, S, E>)
+{
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = SlabIterMut<'a, K, V, E>;
+
+    fn into_iter(self) -> SlabIterMut<'a, K, V, E> {
+        self.iter_mut()
+    }
+}
+
+impl<
+        K: Hash + Eq,
+        V,
+        S: BuildHasher,
+        E: Epoch,
+        #[cfg(feature = "allocator-api")] A: Clone + Allocator,
+    > fmt::Debug for with_alloc!(SlabLruCache<K, V, S, E>)
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SlabLruCache")
+            .field("len", &self.len())
+            .field("cap", &self.cap())
+            .finish()
+    }
+}
+
+/// An iterator over the entries of a `SlabLruCache`.
+///
+/// This `struct` is created by the [`iter`] method on [`SlabLruCache`][`SlabLruCache`]. See its
+/// documentation for more.
+///
+/// [`iter`]: struct.SlabLruCache.html#method.iter
+/// [`SlabLruCache`]: struct.SlabLruCache.html
+pub struct SlabIter<'a, K: 'a, V: 'a, E: 'a = u64> {
+    slots: &'a [Slot<K, V, E>],
+    front: u32,
+    back: u32,
+    len: usize,
+}
+
+impl<'a, K, V, E> Iterator for SlabIter<'a, K, V, E> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = &self.slots[self.front as usize];
+        self.front = slot.next;
+        self.len -= 1;
+        slot.entry.as_ref().map(|(k, v)| (k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+
+    fn count(self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, K, V, E> DoubleEndedIterator for SlabIter<'a, K, V, E> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = &self.slots[self.back as usize];
+        self.back = slot.prev;
+        self.len -= 1;
+        slot.entry.as_ref().map(|(k, v)| (k, v))
+    }
+}
+
+impl<'a, K, V, E> ExactSizeIterator for SlabIter<'a, K, V, E> {}
+impl<'a, K, V, E> FusedIterator for SlabIter<'a, K, V, E> {}
+
+impl<'a, K, V, E> Clone for SlabIter<'a, K, V, E> {
+    fn clone(&self) -> SlabIter<'a, K, V, E> {
+        SlabIter {
+            slots: self.slots,
+            front: self.front,
+            back: self.back,
+            len: self.len,
+        }
+    }
+}
+
+/// An iterator over mutables entries of a `SlabLruCache`.
+///
+/// This `struct` is created by the [`iter_mut`] method on [`SlabLruCache`][`SlabLruCache`]. See
+/// its documentation for more.
+///
+/// [`iter_mut`]: struct.SlabLruCache.html#method.iter_mut
+/// [`SlabLruCache`]: struct.SlabLruCache.html
+pub struct SlabIterMut<'a, K: 'a, V: 'a, E: 'a = u64> {
+    // the iterator hands out mutable references into distinct slots, which a slice borrow
+    // cannot express
+    slots: *mut Slot<K, V, E>,
+    front: u32,
+    back: u32,
+    len: usize,
+
+    phantom: PhantomData<&'a mut Slot<K, V, E>>,
+}
+
+impl<'a, K, V, E> Iterator for SlabIterMut<'a, K, V, E> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = unsafe { &mut *self.slots.add(self.front as usize) };
+        self.front = slot.next;
+        self.len -= 1;
+        slot.entry.as_mut().map(|(k, v)| (&*k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+
+    fn count(self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, K, V, E> DoubleEndedIterator for SlabIterMut<'a, K, V, E> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = unsafe { &mut *self.slots.add(self.back as usize) };
+        self.back = slot.prev;
+        self.len -= 1;
+        slot.entry.as_mut().map(|(k, v)| (&*k, v))
+    }
+}
+
+impl<'a, K, V, E> ExactSizeIterator for SlabIterMut<'a, K, V, E> {}
+impl<'a, K, V, E> FusedIterator for SlabIterMut<'a, K, V, E> {}
+
+unsafe impl<'a, K: Send, V: Send, E: Send> Send for SlabIterMut<'a, K, V, E> {}
+unsafe impl<'a, K: Sync, V: Sync, E: Sync> Sync for SlabIterMut<'a, K, V, E> {}
+
+/// An iterator that moves out of a `SlabLruCache`.
+///
+/// This `struct` is created by the [`into_iter`] method on [`SlabLruCache`][`SlabLruCache`].
+/// See its documentation for more.
+///
+/// [`into_iter`]: struct.SlabLruCache.html#method.into_iter
+/// [`SlabLruCache`]: struct.SlabLruCache.html
+pub struct SlabIntoIter<K, V, E = u64>
+where
+    K: Hash + Eq,
+    E: Epoch,
+{
+    cache: SlabLruCache<K, V, DefaultHasher, E>,
+}
+
+impl<K, V, E> Iterator for SlabIntoIter<K, V, E>
+where
+    K: Hash + Eq,
+    E: Epoch,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        self.cache.pop_lru()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.cache.len();
+        (len, Some(len))
+    }
+
+    fn count(self) -> usize {
+        self.cache.len()
+    }
+}
+
+impl<K, V, E> ExactSizeIterator for SlabIntoIter<K, V, E>
+where
+    K: Hash + Eq,
+    E: Epoch,
+{
+}
+impl<K, V, E> FusedIterator for SlabIntoIter<K, V, E>
+where
+    K: Hash + Eq,
+    E: Epoch,
+{
+}
+
+impl<K: Hash + Eq, V, E: Epoch> IntoIterator for SlabLruCache<K, V, DefaultHasher, E> {
+    type Item = (K, V);
+    type IntoIter = SlabIntoIter<K, V, E>;
+
+    fn into_iter(self) -> SlabIntoIter<K, V, E> {
+        SlabIntoIter { cache: self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SlabLruCache;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_put_and_get() {
+        let mut cache = SlabLruCache::new(2);
+        assert_eq!(cache.put("apple", "red"), None);
+        assert_eq!(cache.put("banana", "yellow"), None);
+        assert_eq!(cache.put("apple", "green"), Some("red"));
+
+        assert_eq!(cache.cap(), 2);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"apple"), Some(&"green"));
+        assert_eq!(cache.get(&"banana"), Some(&"yellow"));
+
+        assert_eq!(cache.push("pear", "green"), Some(("apple", "green")));
+        assert!(!cache.contains(&"apple"));
+        assert_eq!(cache.peek_lru(), Some((&"banana", &"yellow")));
+    }
+
+    #[test]
+    fn test_slots_are_reused() {
+        let mut cache = SlabLruCache::new(3);
+        for i in 0..100 {
+            cache.put(i, i);
+            if i % 7 == 0 {
+                cache.pop(&i);
+            }
+        }
+        assert!(cache.slots.len() <= 3);
+        assert_eq!(
+            cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![99, 97, 96]
+        );
+        assert_eq!(
+            cache.iter().rev().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![96, 97, 99]
+        );
+    }
+
+    #[test]
+    fn test_get_moves_to_front() {
+        let mut cache = SlabLruCache::unbounded();
+        for i in 0..4 {
+            cache.put(i, i * 10);
+        }
+        cache.get(&0);
+        *cache.get_mut(&1).unwrap() += 1;
+        *cache.peek_mut(&3).unwrap() += 1;
+
+        assert_eq!(
+            cache.iter().collect::<Vec<_>>(),
+            vec![(&1, &11), (&0, &0), (&3, &31), (&2, &20)]
+        );
+        for (_, v) in cache.iter_mut().rev().take(2) {
+            *v = 0;
+        }
+        assert_eq!(cache.pop_lru(), Some((2, 0)));
+        assert_eq!(cache.pop_lru(), Some((3, 0)));
+    }
+
+    #[test]
+    fn test_evict_by_epoch() {
+        let mut cache = SlabLruCache::new(4);
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.update_epoch(1);
+        cache.put(3, "c");
+        cache.get(&1);
+
+        assert_eq!(cache.epoch_of(&1), Some(1));
+        assert_eq!(cache.pop_lru_by_epoch(1), Some((2, "b", 0)));
+        assert_eq!(cache.pop_lru_by_epoch(1), None);
+        cache.update_epoch(2);
+        cache.evict_by_epoch(2);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_values_are_dropped() {
+        let value = Rc::new(());
+        let mut cache = SlabLruCache::new(2);
+        for i in 0..5 {
+            cache.put(i, value.clone());
+        }
+        assert_eq!(Rc::strong_count(&value), 3);
+        cache.resize(1);
+        assert_eq!(Rc::strong_count(&value), 2);
+        cache.clear();
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        cache.put(0, value.clone());
+        drop(cache);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_resize_shrinks_the_map() {
+        let mut cache = SlabLruCache::new(1024);
+        for i in 0..1024 {
+            cache.put(i, i);
+        }
+        let before = cache.map.capacity();
+        cache.resize(8);
+        assert!(cache.map.capacity() < before);
+        assert_eq!(
+            cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            (1016..1024).rev().collect::<Vec<_>>()
+        );
+        assert_eq!(cache.get(&1020), Some(&1020));
+        cache.put(0, 0);
+        assert!(!cache.contains(&1016));
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut cache = SlabLruCache::unbounded_with_epoch(3);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+        cache.get(&"a");
+
+        let mut iter = cache.into_iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next(), Some(("b", 2)));
+        assert_eq!(iter.collect::<Vec<_>>(), vec![("c", 3), ("a", 1)]);
+    }
+
+    #[test]
+    #[cfg(feature = "allocator-api")]
+    fn test_slab_lives_in_the_given_allocator() {
+        use allocator_api2::alloc::{AllocError, Allocator, Global};
+        use std::alloc::Layout;
+        use std::cell::Cell;
+        use std::ptr::NonNull;
+
+        // counts the allocations made through it
+        #[derive(Clone)]
+        struct Counting(Rc<Cell<usize>>);
+
+        unsafe impl Allocator for Counting {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.set(self.0.get() + 1);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        let count = Rc::new(Cell::new(0));
+        let mut cache = SlabLruCache::unbounded_in(Counting(count.clone()));
+        assert_eq!(count.get(), 0);
+        for i in 0..100 {
+            cache.put(i, i);
+        }
+        assert!(count.get() > 0);
+
+        // a cache with room reserved up front allocates nothing more as it fills up
+        let mut cache = SlabLruCache::new_in(100, Counting(count.clone()));
+        let reserved = count.get();
+        for i in 0..100 {
+            cache.put(i, i);
+        }
+        assert_eq!(count.get(), reserved);
+    }
+
+    #[test]
+    fn test_zero_cap_no_crash() {
+        let mut cache = SlabLruCache::new(0);
+        cache.put("reizeiger", "pointer");
+        assert_eq!(cache.get_or_insert("reizeiger", || "pointer"), None);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+// MIT License
+
+// Copyright (c) 2016 Jerome Froelich
+
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::ops::{Add, AddAssign};
+
+/// A snapshot of the counters an `LruCache` keeps when the `stats` feature is enabled.
+///
+/// Lookups are counted by `get`, `get_mut`, `get_keep_epoch`, `get_mut_keep_epoch`,
+/// `get_or_insert` and `entry`; `peek` and `contains` leave the counters untouched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CacheStats {
+    /// Lookups that found the key.
+    pub hits: u64,
+    /// Lookups that did not find the key.
+    pub misses: u64,
+    /// Entries added under a key that was not in the cache.
+    pub inserts: u64,
+    /// Values replaced under a key that was already in the cache, by a put on that key or by
+    /// `OccupiedEntry::insert`. A put whose charge exceeds the capacity counts here too, even
+    /// though it removes the entry instead of storing the new value.
+    pub updates: u64,
+    /// Entries evicted to make room for other entries.
+    pub capacity_evictions: u64,
+    /// Entries evicted by `evict_by_epoch` or `pop_lru_by_epoch`.
+    pub epoch_evictions: u64,
+    /// Entries removed by `pop`, `pop_entry`, `pop_lru`, `retain`, `retain_with_epoch`,
+    /// `extract_if`, `OccupiedEntry::remove`, `OccupiedEntry::remove_entry` or
+    /// `CursorMut::remove_current`.
+    pub explicit_pops: u64,
+    /// Entries reclaimed after outliving their time-to-live.
+    pub expirations: u64,
+    /// Entries turned away by the admission filter.
+    pub rejections: u64,
+}
+
+impl CacheStats {
+    /// Returns the fraction of lookups that were hits, or `0.0` if there were no lookups.
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+impl Add for CacheStats {
+    type Output = CacheStats;
+
+    fn add(mut self, other: CacheStats) -> CacheStats {
+        self += other;
+        self
+    }
+}
+
+impl AddAssign for CacheStats {
+    fn add_assign(&mut self, other: CacheStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.inserts += other.inserts;
+        self.updates += other.updates;
+        self.capacity_evictions += other.capacity_evictions;
+        self.epoch_evictions += other.epoch_evictions;
+        self.explicit_pops += other.explicit_pops;
+        self.expirations += other.expirations;
+        self.rejections += other.rejections;
+    }
+}